@@ -25,19 +25,20 @@ def maybe_slugify(value: str | None | TranslatableStr) -> str | None:
     return SLUGIFY_REGEX.sub("-", value.lower()).strip("-")
 
 
-def unlocalise(value: str | list[Any] | dict[str, Any]) -> Any:
-    """Recursively unlocalise a dictionary"""
+def unlocalise(value: str | list[Any] | dict[str, Any], language: str = "de") -> Any:
+    """Recursively unlocalise a dictionary, preferring the given language"""
     if isinstance(value, bool | float | int | str) or value is None:
         return value
     if isinstance(value, list):
-        return [unlocalise(v) for v in value]
+        return [unlocalise(v, language) for v in value]
     if isinstance(value, dict):
         # We consider each dict that has only the keys "de" and/or "en" as translated string
         if set(value.keys()) | {"de", "en"} == {"de", "en"}:
+            fallback = "en" if language == "de" else "de"
             # Since we only unlocalise dicts with either en and/or de or {}, the default to {} is fine
-            return value.get("de", value.get("en", {}))
+            return value.get(language, value.get(fallback, {}))
 
-        return {k: unlocalise(v) for k, v in value.items()}
+        return {k: unlocalise(v, language) for k, v in value.items()}
     raise ValueError(f"Unhandled type {type(value)}")
 
 
@@ -50,6 +51,16 @@ def normalise_id(_id: str) -> str | None:
     return ".".join(parts)
 
 
+def extract_floor(_id: str, _type: str) -> str | None:
+    """Extract the floor (e.g. "EG", "01" or "U1") from the id of a room"""
+    if _type not in {"room", "virtual_room"}:
+        return None
+    parts = _id.split(".")
+    if len(parts) != 3:
+        return None
+    return parts[1].upper()
+
+
 def export_for_search(data: dict) -> None:
     """Export a subset of the data for the /search api"""
     export = []
@@ -87,10 +98,12 @@ def export_for_search(data: dict) -> None:
                 "room_code": _id,
                 "room_code_normalised": normalise_id(_id),
                 "name": entry["name"],
+                "name_en": unlocalise(entry["name"], "en"),
                 "arch_name": entry.get("arch_name"),
                 "arch_name_normalised": normalise_id(entry.get("arch_name")),
                 "type": entry["type"],
                 "type_common_name": entry["type_common_name"],
+                "type_common_name_en": unlocalise(entry["type_common_name"], "en"),
                 "facet": {
                     "site": "site",
                     "campus": "site",
@@ -101,18 +114,23 @@ def export_for_search(data: dict) -> None:
                     "virtual_room": "room",
                 }.get(entry["type"]),
                 "operator_name": entry["props"].get("operator", {}).get("name", None),
+                "operator_code": maybe_slugify(entry["props"].get("operator", {}).get("code", None)),
+                "operator_id": entry["props"].get("operator", {}).get("id", None),
                 "parent_building_names": parent_building_names,
+                "parent_building_names_en": unlocalise(parent_building_names, "en"),
                 # For all other parents, only the ids and their keywords (TODO) are searchable
                 "parent_keywords": [maybe_slugify(value) for value in parent_building_names + entry["parents"][1:]],
                 "campus": maybe_slugify(campus_name),
                 "address": address.get("street", None) if isinstance(address, dict) else address.street,
                 "usage": maybe_slugify(entry.get("usage", {}).get("name", None)),
+                "floor": extract_floor(_id, entry["type"]),
+                "has_calendar": bool(entry["props"].get("calendar_url")),
                 "rank": int(entry["ranking_factors"]["rank_combined"]),
                 **geo,
             },
         )
 
-    # the data contains translations, currently we don't allow these in the search api
+    # the data contains translations, the english ones were already extracted into the *_en fields
     export = unlocalise(export)
 
     _make_sure_is_safe(export)
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS search_misses
+(
+    id          BIGSERIAL PRIMARY KEY,
+    query       TEXT        NOT NULL,
+    tokens      JSONB       NOT NULL,
+    filters     TEXT        NOT NULL,
+    reason      TEXT        NOT NULL CHECK (reason IN ('zero_hits', 'low_position')),
+    picked_id   TEXT,
+    position    INTEGER,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+COMMENT ON TABLE search_misses IS 'search queries which did not find anything or where the user had to pick a result far down the list';
+COMMENT ON COLUMN search_misses.tokens IS 'the text tokens of the query, as understood by the search';
+COMMENT ON COLUMN search_misses.filters IS 'the filters of the query, as passed to meilisearch';
+COMMENT ON COLUMN search_misses.position IS 'zero-based position of the picked result, only present for low_position';
+
+CREATE INDEX IF NOT EXISTS search_misses_recorded_at_idx ON search_misses (recorded_at);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- room ids are looked up case-insensitively by the search
+CREATE INDEX IF NOT EXISTS aliases_lower_alias_idx ON aliases (LOWER(alias));
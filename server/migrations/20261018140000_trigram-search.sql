@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- used for searching when meilisearch is unavailable
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS de_name_trgm_idx ON de USING GIN (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS en_name_trgm_idx ON en USING GIN (name gin_trgm_ops);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS calendar_syncs
+(
+    id        BIGSERIAL PRIMARY KEY,
+    room_code TEXT        NOT NULL,
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    added     INTEGER     NOT NULL,
+    changed   INTEGER     NOT NULL,
+    removed   INTEGER     NOT NULL
+);
+COMMENT ON TABLE calendar_syncs IS 'how many events of a room each calendar sync added, changed or removed';
+
+CREATE INDEX IF NOT EXISTS calendar_syncs_room_code_synced_at_idx ON calendar_syncs (room_code, synced_at);
@@ -0,0 +1,23 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS calendar_changes
+(
+    id                 BIGSERIAL PRIMARY KEY,
+    event_id           INTEGER     NOT NULL,
+    kind               TEXT        NOT NULL CHECK (kind IN ('created', 'moved', 'cancelled')),
+    room_code          TEXT        NOT NULL,
+    start_at           TIMESTAMPTZ NOT NULL,
+    end_at             TIMESTAMPTZ NOT NULL,
+    title_de           TEXT        NOT NULL,
+    title_en           TEXT        NOT NULL,
+    previous_room_code TEXT,
+    previous_start_at  TIMESTAMPTZ,
+    previous_end_at    TIMESTAMPTZ,
+    changed_at         TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+COMMENT ON TABLE calendar_changes IS 'events which were created, moved or cancelled, as noticed while syncing the calendars';
+COMMENT ON COLUMN calendar_changes.room_code IS 'where the event takes place after the change (or took place, if it was cancelled)';
+COMMENT ON COLUMN calendar_changes.previous_room_code IS 'where the event took place before it was moved, only present for moved';
+
+CREATE INDEX IF NOT EXISTS calendar_changes_room_code_changed_at_idx ON calendar_changes (room_code, changed_at);
+CREATE INDEX IF NOT EXISTS calendar_changes_previous_room_code_changed_at_idx ON calendar_changes (previous_room_code, changed_at);
+CREATE INDEX IF NOT EXISTS calendar_changes_event_id_idx ON calendar_changes (event_id);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE calendar_syncs
+    ADD COLUMN IF NOT EXISTS failed INTEGER NOT NULL DEFAULT 0;
+COMMENT ON COLUMN calendar_syncs.failed IS 'how many of the added or changed events could not be stored';
@@ -1,9 +1,10 @@
+use crate::db::calendar_changes::{ChangeKind, EventChange, PreviousSlot};
 use crate::external::connectum::ConnectumEvent;
 use crate::limited::hash_map::LimitedHashMap;
 use crate::limited::vec::LimitedVec;
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
-use std::collections::HashMap;
+use sqlx::{Connection, PgExecutor, PgPool};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use tracing::debug;
 use tracing::error;
@@ -33,6 +34,102 @@ impl CalendarLocation {
             .await?;
         Ok(LimitedVec(res))
     }
+    /// Keys of the rooms with a calendar, which are not known to be free during `start`..`end`, sorted
+    ///
+    /// If `parents` or `usages` (both slugified, as in the search filters) are not empty, only rooms inside one of the `parents` or with one of the `usages` are included.
+    /// Rooms, whose calendar was never scraped, are included, as we cannot know whether they are free.
+    #[tracing::instrument(skip(pool))]
+    pub(crate) async fn fetch_unavailable_keys(
+        pool: &PgPool,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        parents: &[String],
+        usages: &[String],
+    ) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"
+        SELECT d.key
+        FROM de d
+        WHERE d.calendar_url IS NOT NULL
+          AND (CARDINALITY($3::text[]) = 0
+               OR EXISTS (SELECT 1
+                          FROM JSONB_ARRAY_ELEMENTS_TEXT((d.data -> 'parents') || (d.data -> 'parent_names')) AS p(parent)
+                          WHERE LOWER(p.parent) = ANY ($3::text[])
+                             OR TRIM(BOTH '-' FROM REGEXP_REPLACE(LOWER(p.parent), '[^a-z0-9äöüß.-]+', '-', 'g')) = ANY ($3::text[])))
+          AND (CARDINALITY($4::text[]) = 0
+               OR TRIM(BOTH '-' FROM REGEXP_REPLACE(LOWER(d.data -> 'usage' ->> 'name'), '[^a-z0-9äöüß.-]+', '-', 'g')) = ANY ($4::text[]))
+          AND (d.last_calendar_scrape_at IS NULL
+               OR EXISTS (SELECT 1
+                          FROM calendar c
+                          WHERE c.room_code = d.key AND c.start_at < $2 AND c.end_at > $1))
+        ORDER BY d.key"#,
+            start,
+            end,
+            parents,
+            usages,
+        )
+        .fetch_all(pool)
+        .await
+    }
+    /// When the calendar of any room was scraped the last time
+    #[tracing::instrument(skip(pool))]
+    pub(crate) async fn fetch_last_scrape_at(pool: &PgPool) -> sqlx::Result<Option<DateTime<Utc>>> {
+        sqlx::query_scalar!("SELECT MAX(last_calendar_scrape_at) FROM de")
+            .fetch_one(pool)
+            .await
+    }
+    /// All rooms inside `parent`, with a scraped calendar and no event overlapping `start`..`end`
+    ///
+    /// `parent` is matched against the keys and short names of all parents (e.g. `mi` or `MW`),
+    /// `usage` against the (german and english) usage names.
+    /// The most relevant rooms are returned first.
+    #[tracing::instrument(skip(pool))]
+    pub(crate) async fn fetch_free(
+        pool: &PgPool,
+        parent: &str,
+        usage: Option<&str>,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        should_use_english: bool,
+        limit: i64,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+        SELECT d.key,
+               CASE WHEN $5 THEN COALESCE(e.name, d.name) ELSE d.name END                      AS "name!",
+               d.last_calendar_scrape_at,
+               d.calendar_url,
+               d.type,
+               CASE WHEN $5 THEN COALESCE(e.type_common_name, d.type_common_name)
+                    ELSE d.type_common_name END                                                  AS "type_common_name!"
+        FROM de d
+        LEFT JOIN en e ON e.key = d.key
+        LEFT JOIN ranking_factors r ON r.id = d.key
+        WHERE d.calendar_url IS NOT NULL
+          AND d.last_calendar_scrape_at IS NOT NULL
+          AND d.key IN (SELECT p.key
+                        FROM parents p
+                        JOIN de b ON b.key = p.id
+                        WHERE b.key = LOWER($1) OR LOWER(b.data ->> 'short_name') = LOWER($1))
+          AND ($2::text IS NULL
+               OR POSITION(LOWER($2) IN LOWER(d.data -> 'usage' ->> 'name')) > 0
+               OR POSITION(LOWER($2) IN LOWER(e.data -> 'usage' ->> 'name')) > 0)
+          AND NOT EXISTS (SELECT 1
+                          FROM calendar c
+                          WHERE c.room_code = d.key AND c.start_at < $4 AND c.end_at > $3)
+        ORDER BY r.rank_combined DESC NULLS LAST, d.key
+        LIMIT $6"#,
+            parent,
+            usage,
+            start,
+            end,
+            should_use_english,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
 impl Debug for CalendarLocation {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -82,6 +179,7 @@ impl LocationEvents {
     }
 }
 
+#[derive(PartialEq)]
 pub struct Event {
     pub id: i32,
     pub room_code: String,
@@ -94,66 +192,78 @@ pub struct Event {
     pub detailed_entry_type: String,
 }
 impl Event {
+    /// All stored events of the room `room_code`, the earliest first
+    #[tracing::instrument(skip(executor))]
+    pub async fn fetch_all_of(
+        executor: impl PgExecutor<'_>,
+        room_code: &str,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+        SELECT id,room_code,start_at,end_at,title_de,title_en,stp_type,entry_type,detailed_entry_type
+        FROM calendar
+        WHERE room_code = $1
+        ORDER BY start_at, id"#,
+            room_code
+        )
+        .fetch_all(executor)
+        .await
+    }
+    /// All stored events of the room `room_code`, which overlap `start`..`end`, the earliest first
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_overlapping(
+        pool: &PgPool,
+        room_code: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+        SELECT id,room_code,start_at,end_at,title_de,title_en,stp_type,entry_type,detailed_entry_type
+        FROM calendar
+        WHERE room_code = $1 AND start_at < $3 AND end_at > $2
+        ORDER BY start_at, id"#,
+            room_code,
+            start,
+            end
+        )
+        .fetch_all(pool)
+        .await
+    }
+    /// Replaces the stored events of the room `id` with `events`
+    ///
+    /// Only events, which were added, changed or removed since the last sync are touched.
+    /// Events, which cannot be stored (e.g. because they are in an unknown room), are counted as [`SyncStats::failed`] instead of failing the sync.
+    /// If the changes cannot be recorded or the removed events cannot be deleted, the sync of the room is rolled back.
     #[tracing::instrument(skip(pool))]
     pub async fn store_all(
         pool: &PgPool,
         events: LimitedVec<Event>,
         id: &str,
-    ) -> anyhow::Result<()> {
-        // insert into db
+    ) -> anyhow::Result<SyncStats> {
         let mut tx = pool.begin().await?;
-        if let Err(e) = Event::delete(&mut tx, id).await {
-            error!(error = ?e, "could not delete existing events");
-            tx.rollback().await?;
-            return Err(e.into());
-        }
-        let mut failed: Option<(usize, sqlx::Error)> = None;
-        for event in events.0.iter() {
-            // conflicts cannot occur because all values for said room were dropped
-            if let Err(e) = event.store(&mut tx).await {
-                failed = match failed {
-                    Some((i, e0)) => Some((i + 1, e0)),
-                    None => Some((1, e)),
-                };
+        let stored = Event::fetch_all_of(&mut *tx, id).await?;
+        // on the first sync, every event would be recorded as created
+        let is_first_sync = !SyncStats::any_stored(&mut *tx, id).await?;
+        let diff = EventDiff::between(&stored, &events.0);
+        let failed = match diff.apply(&mut tx, id, !is_first_sync).await {
+            Ok(failed) => failed,
+            Err(e) => {
+                error!(error = ?e, "could not apply the changed events");
+                tx.rollback().await?;
+                return Err(e.into());
             }
-        }
-        if let Some((cnt, e)) = failed {
-            warn!(
-                error = ?e,
-                cnt,
-                total = events.len(),
-                "events could not be inserted because",
-            );
-        }
+        };
+        let stats = SyncStats {
+            failed,
+            ..diff.stats()
+        };
+        stats.store(&mut tx, id).await?;
         tx.commit().await?;
-        debug!(?id, "finished inserting into the db");
-        Ok(())
-    }
-    #[tracing::instrument(skip(tx))]
-    async fn delete(
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-        id: &str,
-    ) -> Result<(), sqlx::Error> {
-        loop {
-            // deliberately somewhat low to not have too long blocking segments
-            let res = sqlx::query!(
-                r#"
-                    WITH rows_to_delete AS (
-                        SELECT id
-                        FROM calendar WHERE room_code = $1
-                        LIMIT 1000
-                    )
-                    
-                    DELETE FROM calendar
-                    WHERE id IN (SELECT id FROM rows_to_delete);"#,
-                id
-            )
-            .execute(&mut **tx)
-            .await?;
-            if res.rows_affected() == 0 {
-                return Ok(());
-            }
-        }
+        debug!(?id, ?stats, "finished syncing the events into the db");
+        Ok(stats)
     }
     #[tracing::instrument(skip(pool))]
     pub async fn update_last_calendar_scrape_at(
@@ -207,6 +317,181 @@ impl Event {
     }
 }
 
+/// What has to change to get from the stored events of a room to the fetched ones
+///
+/// Events are matched by their `id`.
+#[derive(Debug)]
+struct EventDiff<'a> {
+    added: Vec<&'a Event>,
+    /// The stored and the fetched version of each changed event
+    changed: Vec<(&'a Event, &'a Event)>,
+    removed: Vec<&'a Event>,
+    /// The end of the latest stored event, i.e. how far into the future the previous sync reached
+    horizon: Option<DateTime<Utc>>,
+}
+impl<'a> EventDiff<'a> {
+    fn between(stored: &'a [Event], fetched: &'a [Event]) -> Self {
+        let stored_by_id = stored
+            .iter()
+            .map(|e| (e.id, e))
+            .collect::<HashMap<i32, &Event>>();
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for event in fetched {
+            match stored_by_id.get(&event.id) {
+                None => added.push(event),
+                Some(stored) if *stored != event => changed.push((*stored, event)),
+                Some(_) => {}
+            }
+        }
+        let fetched_ids = fetched.iter().map(|e| e.id).collect::<HashSet<i32>>();
+        let removed = stored
+            .iter()
+            .filter(|e| !fetched_ids.contains(&e.id))
+            .collect();
+        Self {
+            added,
+            changed,
+            removed,
+            horizon: stored.iter().map(|e| e.end_at).max(),
+        }
+    }
+    /// Applies the diff to the stored events of the room `room_code`
+    ///
+    /// Each added or changed event is stored in its own savepoint, so that one event, which cannot be stored, does not abort the transaction.
+    /// Returns how many of them could not be stored.
+    async fn apply(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        room_code: &str,
+        record_changes: bool,
+    ) -> sqlx::Result<usize> {
+        // moves between rooms are detected using the events, which are not yet updated
+        if record_changes {
+            self.record_changes(tx, room_code).await?;
+        }
+        if !self.removed.is_empty() {
+            let removed_ids = self.removed.iter().map(|e| e.id).collect::<Vec<i32>>();
+            sqlx::query!(
+                "DELETE FROM calendar WHERE room_code = $1 AND id = ANY($2)",
+                room_code,
+                &removed_ids
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        let upserted = self.changed.iter().map(|(_, fetched)| fetched);
+        let mut failed: Option<(usize, sqlx::Error)> = None;
+        for event in self.added.iter().chain(upserted) {
+            let mut savepoint = tx.begin().await?;
+            match event.store(&mut savepoint).await {
+                Ok(_) => savepoint.commit().await?,
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    failed = match failed {
+                        Some((cnt, e0)) => Some((cnt + 1, e0)),
+                        None => Some((1, e)),
+                    };
+                }
+            }
+        }
+        let Some((cnt, e)) = failed else {
+            return Ok(0);
+        };
+        warn!(
+            error = ?e,
+            cnt,
+            total = self.added.len() + self.changed.len(),
+            "events could not be stored because",
+        );
+        Ok(cnt)
+    }
+    /// Records the changes relevant to people visiting the room in its history
+    ///
+    /// Changes only caused by the scraped time window moving on are not recorded:
+    /// Events appearing after the [`EventDiff::horizon`] are not `created` and events disappearing after they ended are not `cancelled`.
+    async fn record_changes(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        room_code: &str,
+    ) -> sqlx::Result<()> {
+        for event in &self.added {
+            let previous = match EventChange::fetch_previous_slot(tx, event.id, room_code).await? {
+                Some(previous) => Some(previous),
+                None => EventChange::remove_cancellation(tx, event.id, room_code).await?,
+            };
+            match previous {
+                Some(previous) => {
+                    EventChange::store(tx, ChangeKind::Moved, event, Some(&previous)).await?
+                }
+                None if self.horizon.is_some_and(|h| event.start_at < h) => {
+                    EventChange::store(tx, ChangeKind::Created, event, None).await?
+                }
+                None => {}
+            }
+        }
+        for (stored, fetched) in &self.changed {
+            if stored.start_at != fetched.start_at || stored.end_at != fetched.end_at {
+                let previous = PreviousSlot::from(*stored);
+                EventChange::store(tx, ChangeKind::Moved, fetched, Some(&previous)).await?;
+            }
+        }
+        let now = Utc::now();
+        for event in self.removed.iter().filter(|e| e.end_at > now) {
+            EventChange::store(tx, ChangeKind::Cancelled, event, None).await?;
+        }
+        Ok(())
+    }
+    fn stats(&self) -> SyncStats {
+        SyncStats {
+            added: self.added.len(),
+            changed: self.changed.len(),
+            removed: self.removed.len(),
+            failed: 0,
+        }
+    }
+}
+
+/// How many events of a room a sync added, changed or removed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStats {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    /// How many of the added or changed events could not be stored
+    pub failed: usize,
+}
+impl SyncStats {
+    /// If the room `room_code` was synced before
+    #[tracing::instrument(skip(executor))]
+    async fn any_stored(executor: impl PgExecutor<'_>, room_code: &str) -> sqlx::Result<bool> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM calendar_syncs WHERE room_code = $1) AS "any_stored!""#,
+            room_code
+        )
+        .fetch_one(executor)
+        .await
+    }
+    #[tracing::instrument(skip(tx))]
+    async fn store(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        room_code: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO calendar_syncs (room_code, added, changed, removed, failed) VALUES ($1, $2, $3, $4, $5)",
+            room_code,
+            i32::try_from(self.added).unwrap_or(i32::MAX),
+            i32::try_from(self.changed).unwrap_or(i32::MAX),
+            i32::try_from(self.removed).unwrap_or(i32::MAX),
+            i32::try_from(self.failed).unwrap_or(i32::MAX),
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
+
 impl Debug for Event {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let duration = (self.end_at - self.start_at).num_minutes();
@@ -259,3 +544,313 @@ impl Display for EventType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn event(id: i32, start_hour: u32) -> Event {
+        Event {
+            id,
+            room_code: "5602.EG.001".into(),
+            start_at: Utc.with_ymd_and_hms(2024, 7, 1, start_hour, 0, 0).unwrap(),
+            end_at: Utc
+                .with_ymd_and_hms(2024, 7, 1, start_hour + 1, 0, 0)
+                .unwrap(),
+            title_de: "Quantenteleportation".into(),
+            title_en: "Quantum teleportation".into(),
+            stp_type: None,
+            entry_type: EventType::Lecture.to_string(),
+            detailed_entry_type: "Abhaltung".into(),
+        }
+    }
+
+    #[test]
+    fn diff_only_contains_changes() {
+        let stored = vec![event(1, 8), event(2, 10), event(3, 12)];
+        let fetched = vec![event(1, 8), event(2, 14), event(4, 16)];
+        let diff = EventDiff::between(&stored, &fetched);
+        assert_eq!(diff.added.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(
+            diff.changed.iter().map(|(_, e)| e.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(
+            diff.removed.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(
+            diff.stats(),
+            SyncStats {
+                added: 1,
+                changed: 1,
+                removed: 1,
+                failed: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn diff_of_unchanged_events_is_empty() {
+        let stored = vec![event(1, 8), event(2, 10)];
+        let fetched = vec![event(2, 10), event(1, 8)];
+        let diff = EventDiff::between(&stored, &fetched);
+        assert_eq!(diff.stats(), SyncStats::default());
+    }
+
+    #[test]
+    fn horizon_is_the_end_of_the_latest_stored_event() {
+        let stored = vec![event(2, 10), event(1, 8)];
+        let diff = EventDiff::between(&stored, &[]);
+        assert_eq!(diff.horizon, Some(stored[0].end_at));
+        assert_eq!(EventDiff::between(&[], &stored).horizon, None);
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use chrono::{Duration, TimeZone};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::setup::tests::PostgresTestContainer;
+
+    /// A room with a calendar inside `parents` (id and name), which was scraped at `last_calendar_scrape_at`
+    async fn insert_room(
+        pool: &PgPool,
+        key: &str,
+        usage: &str,
+        parents: &[(&str, &str)],
+        last_calendar_scrape_at: Option<DateTime<Utc>>,
+    ) {
+        let data = json!({
+            "name": format!("{key} ({usage})"),
+            "type": "room",
+            "type_common_name": usage,
+            "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"},
+            "parents": parents.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            "parent_names": parents.iter().map(|(_, name)| name).collect::<Vec<_>>(),
+            "usage": {"name": usage},
+            "props": {"calendar_url": format!("https://campus.tum.de/{key}")},
+        });
+        for lang in ["de", "en"] {
+            let query =
+                format!("INSERT INTO {lang}(key,data,last_calendar_scrape_at) VALUES ($1,$2,$3)");
+            sqlx::query(&query)
+                .bind(key)
+                .bind(&data)
+                .bind(last_calendar_scrape_at)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn insert_event(pool: &PgPool, id: i32, room_code: &str, start_at: DateTime<Utc>) {
+        let event = Event {
+            id,
+            room_code: room_code.into(),
+            start_at,
+            end_at: start_at + Duration::hours(2),
+            title_de: "Quantenteleportation".into(),
+            title_en: "Quantum teleportation".into(),
+            stp_type: None,
+            entry_type: EventType::Lecture.to_string(),
+            detailed_entry_type: "Abhaltung".into(),
+        };
+        let mut tx = pool.begin().await.unwrap();
+        event.store(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn syncing_applies_the_diff() {
+        let pg = PostgresTestContainer::new().await;
+        let scraped_at = Utc.with_ymd_and_hms(2024, 7, 1, 6, 0, 0).unwrap();
+        insert_room(&pg.pool, "5602.EG.001", "Hörsaal", &[], Some(scraped_at)).await;
+        let event = |id, room_code: &str, start_hour| Event {
+            id,
+            room_code: room_code.into(),
+            start_at: Utc.with_ymd_and_hms(2024, 7, 1, start_hour, 0, 0).unwrap(),
+            end_at: Utc
+                .with_ymd_and_hms(2024, 7, 1, start_hour + 1, 0, 0)
+                .unwrap(),
+            title_de: "Quantenteleportation".into(),
+            title_en: "Quantum teleportation".into(),
+            stp_type: None,
+            entry_type: EventType::Lecture.to_string(),
+            detailed_entry_type: "Abhaltung".into(),
+        };
+        let stored_slots = || async {
+            Event::fetch_all_of(&pg.pool, "5602.EG.001")
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.id, e.start_at.format("%H:%M").to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let events = vec![
+            event(1, "5602.EG.001", 8),
+            event(2, "5602.EG.001", 10),
+            event(3, "5602.EG.001", 12),
+        ];
+        let stats = Event::store_all(&pg.pool, LimitedVec(events), "5602.EG.001")
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            SyncStats {
+                added: 3,
+                ..SyncStats::default()
+            }
+        );
+
+        // 1 is unchanged, 2 is moved, 3 is removed and 4 is added
+        // 5 is in a room we don't know, so it cannot be stored, but this does not prevent the other changes
+        let events = vec![
+            event(1, "5602.EG.001", 8),
+            event(2, "5602.EG.001", 14),
+            event(4, "5602.EG.001", 16),
+            event(5, "5602.EG.999", 16),
+        ];
+        let stats = Event::store_all(&pg.pool, LimitedVec(events), "5602.EG.001")
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            SyncStats {
+                added: 2,
+                changed: 1,
+                removed: 1,
+                failed: 1,
+            }
+        );
+        assert_eq!(
+            stored_slots().await,
+            vec![
+                (1, "08:00".to_string()),
+                (2, "14:00".to_string()),
+                (4, "16:00".to_string())
+            ]
+        );
+        let syncs: Vec<(i32, i32, i32, i32)> = sqlx::query_as(
+            "SELECT added, changed, removed, failed FROM calendar_syncs WHERE room_code = $1 ORDER BY id",
+        )
+        .bind("5602.EG.001")
+        .fetch_all(&pg.pool)
+        .await
+        .unwrap();
+        assert_eq!(syncs, vec![(3, 0, 0, 0), (2, 1, 1, 1)]);
+
+        // syncing the same events again changes nothing
+        let events = vec![
+            event(1, "5602.EG.001", 8),
+            event(2, "5602.EG.001", 14),
+            event(4, "5602.EG.001", 16),
+        ];
+        let stats = Event::store_all(&pg.pool, LimitedVec(events), "5602.EG.001")
+            .await
+            .unwrap();
+        assert_eq!(stats, SyncStats::default());
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn unavailable_rooms_are_scoped_to_the_filters() {
+        let pg = PostgresTestContainer::new().await;
+        let scraped_at = Utc.with_ymd_and_hms(2024, 7, 1, 6, 0, 0).unwrap();
+        let at = |hour| Utc.with_ymd_and_hms(2024, 7, 1, hour, 0, 0).unwrap();
+        let garching = [
+            ("root", "Standorte"),
+            ("garching", "Garching Forschungszentrum"),
+            ("mi", "Informatik"),
+            ("5602", "Finger 02 (BT02)"),
+        ];
+        let stammgelaende = [
+            ("root", "Standorte"),
+            ("stammgelaende", "Stammgelände"),
+            ("0101", "N1 (Elektrotechnik)"),
+        ];
+        insert_room(
+            &pg.pool,
+            "5602.EG.001",
+            "Hörsaal",
+            &garching,
+            Some(scraped_at),
+        )
+        .await;
+        insert_room(&pg.pool, "5602.EG.002", "Büro", &garching, Some(scraped_at)).await;
+        insert_room(
+            &pg.pool,
+            "5602.EG.003",
+            "Hörsaal",
+            &garching,
+            Some(scraped_at),
+        )
+        .await;
+        insert_room(&pg.pool, "5602.EG.004", "Hörsaal", &garching, None).await;
+        insert_room(
+            &pg.pool,
+            "0101.01.001",
+            "Hörsaal",
+            &stammgelaende,
+            Some(scraped_at),
+        )
+        .await;
+        insert_event(&pg.pool, 1, "5602.EG.001", at(8)).await;
+        insert_event(&pg.pool, 2, "5602.EG.002", at(9)).await;
+        insert_event(&pg.pool, 3, "5602.EG.003", at(14)).await;
+        insert_event(&pg.pool, 4, "0101.01.001", at(8)).await;
+
+        let fetch = |start, end, parents: &[&str], usages: &[&str]| {
+            let parents = parents.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            let usages = usages.iter().map(|u| u.to_string()).collect::<Vec<_>>();
+            let pool = pg.pool.clone();
+            async move {
+                CalendarLocation::fetch_unavailable_keys(&pool, &start, &end, &parents, &usages)
+                    .await
+                    .unwrap()
+            }
+        };
+        // rooms, whose calendar was never scraped, are never known to be free
+        assert_eq!(
+            fetch(at(8), at(10), &[], &[]).await,
+            vec!["0101.01.001", "5602.EG.001", "5602.EG.002", "5602.EG.004"]
+        );
+        // events only touching the window don't make the room unavailable
+        assert_eq!(fetch(at(11), at(14), &[], &[]).await, vec!["5602.EG.004"]);
+        assert_eq!(
+            fetch(at(8), at(10), &["garching"], &[]).await,
+            vec!["5602.EG.001", "5602.EG.002", "5602.EG.004"]
+        );
+        assert_eq!(
+            fetch(at(8), at(10), &["stammgelaende"], &["hörsaal"]).await,
+            vec!["0101.01.001"]
+        );
+        // parent names are matched slugified, same as in the search
+        assert_eq!(
+            fetch(at(8), at(10), &["n1-elektrotechnik"], &[]).await,
+            vec!["0101.01.001"]
+        );
+        assert_eq!(
+            fetch(at(8), at(10), &["finger-02-bt02"], &["hörsaal"]).await,
+            vec!["5602.EG.001", "5602.EG.004"]
+        );
+        assert_eq!(
+            fetch(at(8), at(10), &["mi"], &["büro"]).await,
+            vec!["5602.EG.002"]
+        );
+        assert_eq!(
+            CalendarLocation::fetch_last_scrape_at(&pg.pool)
+                .await
+                .unwrap(),
+            Some(scraped_at)
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::{Display, Formatter};
+
+use crate::db::calendar::Event;
+use crate::limited::vec::LimitedVec;
+
+/// How an event changed between two syncs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    /// The event now takes place at a different time or in a different room
+    Moved,
+    Cancelled,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeKind::Created => write!(f, "created"),
+            ChangeKind::Moved => write!(f, "moved"),
+            ChangeKind::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Where and when an event took place before it was moved
+#[derive(Debug, Clone)]
+pub struct PreviousSlot {
+    pub room_code: String,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+}
+impl From<&Event> for PreviousSlot {
+    fn from(event: &Event) -> Self {
+        Self {
+            room_code: event.room_code.clone(),
+            start_at: event.start_at,
+            end_at: event.end_at,
+        }
+    }
+}
+
+/// An entry of the history of changes to the calendar events
+#[derive(Debug, Clone)]
+pub struct EventChange {
+    pub event_id: i32,
+    pub kind: String,
+    pub room_code: String,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    pub title_de: String,
+    pub title_en: String,
+    pub previous: Option<PreviousSlot>,
+    pub changed_at: DateTime<Utc>,
+}
+impl EventChange {
+    /// Changes to events in the room `room_code` (including events moved out of it) since `since`, the oldest first
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_since(
+        pool: &PgPool,
+        room_code: &str,
+        since: &DateTime<Utc>,
+    ) -> sqlx::Result<LimitedVec<Self>> {
+        let rows = sqlx::query!(
+            r#"
+        SELECT event_id,kind,room_code,start_at,end_at,title_de,title_en,previous_room_code,previous_start_at,previous_end_at,changed_at
+        FROM calendar_changes
+        WHERE (room_code = $1 OR previous_room_code = $1) AND changed_at >= $2
+        ORDER BY changed_at, id"#,
+            room_code,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let previous = match (
+                    row.previous_room_code,
+                    row.previous_start_at,
+                    row.previous_end_at,
+                ) {
+                    (Some(room_code), Some(start_at), Some(end_at)) => Some(PreviousSlot {
+                        room_code,
+                        start_at,
+                        end_at,
+                    }),
+                    _ => None,
+                };
+                Self {
+                    event_id: row.event_id,
+                    kind: row.kind,
+                    room_code: row.room_code,
+                    start_at: row.start_at,
+                    end_at: row.end_at,
+                    title_de: row.title_de,
+                    title_en: row.title_en,
+                    previous,
+                    changed_at: row.changed_at,
+                }
+            })
+            .collect())
+    }
+    /// Records, that `event` changed in the way `kind`
+    #[tracing::instrument(skip(tx))]
+    pub(super) async fn store(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        kind: ChangeKind,
+        event: &Event,
+        previous: Option<&PreviousSlot>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO calendar_changes (event_id,kind,room_code,start_at,end_at,title_de,title_en,previous_room_code,previous_start_at,previous_end_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+            event.id,
+            kind.to_string(),
+            event.room_code,
+            event.start_at,
+            event.end_at,
+            event.title_de,
+            event.title_en,
+            previous.map(|p| p.room_code.as_str()),
+            previous.map(|p| p.start_at),
+            previous.map(|p| p.end_at),
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+    /// Where the event `event_id` took place, if it is stored in another room than `room_code`
+    ///
+    /// This is the case, if the event was moved to `room_code` and the other room was not yet synced since.
+    #[tracing::instrument(skip(tx))]
+    pub(super) async fn fetch_previous_slot(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        event_id: i32,
+        room_code: &str,
+    ) -> sqlx::Result<Option<PreviousSlot>> {
+        sqlx::query_as!(
+            PreviousSlot,
+            r#"
+        SELECT room_code, start_at, end_at
+        FROM calendar
+        WHERE id = $1 AND room_code <> $2"#,
+            event_id,
+            room_code
+        )
+        .fetch_optional(&mut **tx)
+        .await
+    }
+    /// Removes the recent cancellation of the event `event_id` in another room than `room_code` from the history
+    ///
+    /// If an event was moved to `room_code` and the other room was synced first, the event was recorded as cancelled there.
+    /// As it was actually moved, the cancellation is removed and where the event would have taken place is returned.
+    #[tracing::instrument(skip(tx))]
+    pub(super) async fn remove_cancellation(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        event_id: i32,
+        room_code: &str,
+    ) -> sqlx::Result<Option<PreviousSlot>> {
+        sqlx::query_as!(
+            PreviousSlot,
+            r#"
+        WITH cancellations AS (
+            DELETE FROM calendar_changes
+            WHERE event_id = $1 AND kind = 'cancelled' AND room_code <> $2 AND changed_at > NOW() - INTERVAL '1 day'
+            RETURNING room_code, start_at, end_at, changed_at
+        )
+        SELECT room_code AS "room_code!", start_at AS "start_at!", end_at AS "end_at!"
+        FROM cancellations
+        ORDER BY changed_at DESC
+        LIMIT 1"#,
+            event_id,
+            room_code
+        )
+        .fetch_optional(&mut **tx)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use chrono::{Duration, DurationRound};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::db::calendar::EventType;
+    use crate::setup::tests::PostgresTestContainer;
+
+    const ROOM: &str = "5602.EG.001";
+    const OTHER_ROOM: &str = "5602.EG.002";
+
+    async fn insert_room(pool: &PgPool, key: &str) {
+        let data = json!({
+            "name": key,
+            "type": "room",
+            "type_common_name": "Hörsaal",
+            "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"},
+            "props": {"calendar_url": format!("https://campus.tum.de/{key}")},
+        });
+        for lang in ["de", "en"] {
+            let query = format!(
+                "INSERT INTO {lang}(key,data,last_calendar_scrape_at) VALUES ($1,$2,NOW())"
+            );
+            sqlx::query(&query)
+                .bind(key)
+                .bind(&data)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Syncs the events of `room_code`, which are given as `(id, room_code, start_at)`
+    async fn sync(pool: &PgPool, room_code: &str, events: &[(i32, &str, DateTime<Utc>)]) {
+        let events = events
+            .iter()
+            .map(|(id, room_code, start_at)| Event {
+                id: *id,
+                room_code: room_code.to_string(),
+                start_at: *start_at,
+                end_at: *start_at + Duration::hours(1),
+                title_de: "Quantenteleportation".into(),
+                title_en: "Quantum teleportation".into(),
+                stp_type: None,
+                entry_type: EventType::Lecture.to_string(),
+                detailed_entry_type: "Abhaltung".into(),
+            })
+            .collect();
+        Event::store_all(pool, LimitedVec(events), room_code)
+            .await
+            .unwrap();
+    }
+
+    /// The history of `room_code` as `(id, kind, room_code, previous room_code)`
+    async fn history(pool: &PgPool, room_code: &str) -> Vec<(i32, String, String, Option<String>)> {
+        let since = Utc::now() - Duration::hours(1);
+        EventChange::fetch_since(pool, room_code, &since)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| {
+                (
+                    c.event_id,
+                    c.kind,
+                    c.room_code,
+                    c.previous.map(|p| p.room_code),
+                )
+            })
+            .collect()
+    }
+
+    fn change(
+        id: i32,
+        kind: &str,
+        room_code: &str,
+        previous: Option<&str>,
+    ) -> (i32, String, String, Option<String>) {
+        (
+            id,
+            kind.into(),
+            room_code.into(),
+            previous.map(String::from),
+        )
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn changes_are_recorded_in_the_history() {
+        let pg = PostgresTestContainer::new().await;
+        insert_room(&pg.pool, ROOM).await;
+        insert_room(&pg.pool, OTHER_ROOM).await;
+        // cancellations are only recorded for events, which did not yet end
+        let tomorrow = Utc::now().duration_trunc(Duration::days(1)).unwrap() + Duration::days(1);
+        let at = |hour| tomorrow + Duration::hours(hour);
+
+        // nothing is recorded on the first sync
+        sync(
+            &pg.pool,
+            ROOM,
+            &[(1, ROOM, at(8)), (2, ROOM, at(10)), (3, ROOM, at(12))],
+        )
+        .await;
+        sync(&pg.pool, OTHER_ROOM, &[]).await;
+        assert!(history(&pg.pool, ROOM).await.is_empty());
+
+        // 1 is unchanged, 2 is moved to a different time, 3 is cancelled and 4 is created
+        // 5 only appears because the synced time window moved on, so it is not recorded as created
+        sync(
+            &pg.pool,
+            ROOM,
+            &[
+                (1, ROOM, at(8)),
+                (2, ROOM, at(14)),
+                (4, ROOM, at(9)),
+                (5, ROOM, at(20)),
+            ],
+        )
+        .await;
+        assert_eq!(
+            history(&pg.pool, ROOM).await,
+            vec![
+                change(4, "created", ROOM, None),
+                change(2, "moved", ROOM, Some(ROOM)),
+                change(3, "cancelled", ROOM, None),
+            ]
+        );
+        let since = Utc::now() - Duration::hours(1);
+        let changes = EventChange::fetch_since(&pg.pool, ROOM, &since)
+            .await
+            .unwrap();
+        let previous = changes.0[1].previous.as_ref().unwrap();
+        assert_eq!((previous.start_at, previous.end_at), (at(10), at(11)));
+
+        // 1 is moved to the other room, but this room is synced first
+        // => it is recorded as cancelled until the other room is synced
+        sync(
+            &pg.pool,
+            ROOM,
+            &[(2, ROOM, at(14)), (4, ROOM, at(9)), (5, ROOM, at(20))],
+        )
+        .await;
+        assert_eq!(
+            history(&pg.pool, ROOM).await.last(),
+            Some(&change(1, "cancelled", ROOM, None))
+        );
+        sync(&pg.pool, OTHER_ROOM, &[(1, OTHER_ROOM, at(8))]).await;
+        assert_eq!(
+            history(&pg.pool, OTHER_ROOM).await,
+            vec![change(1, "moved", OTHER_ROOM, Some(ROOM))]
+        );
+
+        // 2 is moved to the other room, which is synced first
+        sync(
+            &pg.pool,
+            OTHER_ROOM,
+            &[(1, OTHER_ROOM, at(8)), (2, OTHER_ROOM, at(14))],
+        )
+        .await;
+        sync(&pg.pool, ROOM, &[(4, ROOM, at(9)), (5, ROOM, at(20))]).await;
+        assert_eq!(
+            history(&pg.pool, ROOM).await,
+            vec![
+                change(4, "created", ROOM, None),
+                change(2, "moved", ROOM, Some(ROOM)),
+                change(3, "cancelled", ROOM, None),
+                change(1, "moved", OTHER_ROOM, Some(ROOM)),
+                change(2, "moved", OTHER_ROOM, Some(ROOM)),
+            ]
+        );
+    }
+}
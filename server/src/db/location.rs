@@ -68,3 +68,159 @@ impl LocationKeyAlias {
         .await
     }
 }
+
+/// An entry, which a room id unambiguously resolved to
+#[derive(Debug, Clone)]
+pub struct AliasMatch {
+    pub key: String,
+    pub name: String,
+    pub r#type: String,
+    pub type_common_name: String,
+}
+impl AliasMatch {
+    /// The entry with exactly this (case-insensitive) alias, if the alias is unambiguous
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_by_alias(
+        pool: &PgPool,
+        alias: &str,
+        should_use_english: bool,
+    ) -> sqlx::Result<Option<Self>> {
+        let entries = sqlx::query_as!(
+            Self,
+            r#"
+        SELECT DISTINCT d.key,
+                        COALESCE(e.name, d.name)                          AS "name!",
+                        d.type,
+                        COALESCE(e.type_common_name, d.type_common_name)  AS "type_common_name!"
+        FROM aliases a
+        JOIN de d ON d.key = a.key
+        LEFT JOIN en e ON e.key = d.key AND $2
+        WHERE LOWER(a.alias) = LOWER($1)
+        LIMIT 2"#,
+            alias,
+            should_use_english
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(Self::unambiguous(entries))
+    }
+    /// The entry inside the `building`, whose architects name is `arch_id`, if this is unambiguous
+    ///
+    /// The `building` is matched against the keys and short names of all parents (e.g. `mi` or `MW`)
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_by_building_arch_id(
+        pool: &PgPool,
+        building: &str,
+        arch_id: &str,
+        should_use_english: bool,
+    ) -> sqlx::Result<Option<Self>> {
+        let entries = sqlx::query_as!(
+            Self,
+            r#"
+        SELECT DISTINCT d.key,
+                        COALESCE(e.name, d.name)                          AS "name!",
+                        d.type,
+                        COALESCE(e.type_common_name, d.type_common_name)  AS "type_common_name!"
+        FROM aliases a
+        JOIN de d ON d.key = a.key
+        LEFT JOIN en e ON e.key = d.key AND $3
+        JOIN parents p ON p.key = a.key
+        JOIN de b ON b.key = p.id
+        WHERE LOWER(a.alias) LIKE LOWER($2) || '@%'
+          AND (b.key = LOWER($1) OR LOWER(b.data ->> 'short_name') = LOWER($1))
+        LIMIT 2"#,
+            building,
+            arch_id,
+            should_use_english
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(Self::unambiguous(entries))
+    }
+    /// The only entry of `entries`, if there is exactly one
+    fn unambiguous(mut entries: Vec<Self>) -> Option<Self> {
+        match entries.len() {
+            1 => entries.pop(),
+            _ => None,
+        }
+    }
+}
+
+/// An entry, which other entries are located in (e.g. a building or campus)
+#[derive(Debug, Clone)]
+pub struct ParentName {
+    pub key: String,
+    pub name: String,
+    pub short_name: Option<String>,
+}
+impl ParentName {
+    /// All entries, which have at least one child
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_all(pool: &PgPool) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+        SELECT d.key, d.name, d.data ->> 'short_name' AS short_name
+        FROM de d
+        WHERE d.key IN (SELECT DISTINCT p.id FROM parents p)"#
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// An entry, whose name is similar to a query
+///
+/// Only used if meilisearch is unavailable, as this is much less sophisticated.
+#[derive(Debug, Clone)]
+pub struct SimilarLocation {
+    pub key: String,
+    pub name: String,
+    pub r#type: String,
+    pub type_common_name: String,
+    pub parent_name: Option<String>,
+}
+impl SimilarLocation {
+    /// Entries, whose (german or english) name is similar to `q`, the most similar and relevant ones first
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_similar(
+        pool: &PgPool,
+        q: &str,
+        should_use_english: bool,
+        limit: i64,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+        SELECT d.key,
+               CASE WHEN $2 THEN COALESCE(e.name, d.name) ELSE d.name END          AS "name!",
+               d.type,
+               CASE WHEN $2 THEN COALESCE(e.type_common_name, d.type_common_name)
+                    ELSE d.type_common_name END                                      AS "type_common_name!",
+               CASE WHEN $2 THEN COALESCE(e.data, d.data) ELSE d.data END
+                   -> 'parent_names' ->> -1                                          AS parent_name
+        FROM de d
+        LEFT JOIN en e ON e.key = d.key
+        LEFT JOIN ranking_factors r ON r.id = d.key
+        WHERE d.name % $1
+           OR e.name % $1
+           OR d.name ILIKE '%' || $4 || '%'
+        ORDER BY GREATEST(word_similarity($1, d.name), COALESCE(word_similarity($1, e.name), 0)) DESC,
+                 r.rank_combined DESC NULLS LAST
+        LIMIT $3"#,
+            q,
+            should_use_english,
+            limit,
+            escape_like(q),
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Escapes the wildcards of `LIKE` patterns, so that `text` only matches itself
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
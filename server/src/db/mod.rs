@@ -1,3 +1,5 @@
 pub mod calendar;
+pub mod calendar_changes;
 pub mod location;
 pub mod public_transport;
+pub mod search_misses;
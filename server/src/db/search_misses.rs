@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::{Debug, Formatter};
+
+use crate::limited::vec::LimitedVec;
+use crate::search_executor::ParsedQuery;
+
+/// Why a query was recorded as a miss
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissReason {
+    /// The query did not return any results
+    ZeroHits,
+    /// The user picked a result far down the list
+    LowPosition,
+}
+
+impl MissReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MissReason::ZeroHits => "zero_hits",
+            MissReason::LowPosition => "low_position",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchMiss {
+    query: String,
+    parsed: ParsedQuery,
+    reason: MissReason,
+    picked_id: Option<String>,
+    position: Option<i32>,
+}
+
+impl SearchMiss {
+    pub fn zero_hits(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            parsed: ParsedQuery::from(query),
+            reason: MissReason::ZeroHits,
+            picked_id: None,
+            position: None,
+        }
+    }
+    pub fn low_position(query: &str, picked_id: &str, position: usize) -> Self {
+        Self {
+            query: query.to_string(),
+            parsed: ParsedQuery::from(query),
+            reason: MissReason::LowPosition,
+            picked_id: Some(picked_id.to_string()),
+            position: Some(i32::try_from(position).unwrap_or(i32::MAX)),
+        }
+    }
+    #[tracing::instrument(skip(pool))]
+    pub async fn store(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let tokens = serde_json::to_value(&self.parsed.tokens)?;
+        sqlx::query!(
+            r#"INSERT INTO search_misses(query, tokens, filters, reason, picked_id, position)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            self.query,
+            tokens,
+            self.parsed.filters.as_meilisearch_filters(),
+            self.reason.as_str(),
+            self.picked_id,
+            self.position,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// All misses of a query, aggregated
+pub struct MissReport {
+    pub query: String,
+    pub tokens: serde_json::Value,
+    pub filters: String,
+    pub zero_hits: i64,
+    pub low_positions: i64,
+    pub picked_ids: Vec<String>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl Debug for MissReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MissReport")
+            .field("query", &self.query)
+            .field("zero_hits", &self.zero_hits)
+            .field("low_positions", &self.low_positions)
+            .finish()
+    }
+}
+
+impl MissReport {
+    /// The most common misses since `since`, aggregated by their (case-insensitive) query
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch(
+        pool: &PgPool,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<LimitedVec<MissReport>> {
+        let reports = sqlx::query_as!(
+            MissReport,
+            r#"
+SELECT LOWER(TRIM(query))                                         AS "query!",
+       (ARRAY_AGG(tokens ORDER BY recorded_at DESC))[1]           AS "tokens!",
+       (ARRAY_AGG(filters ORDER BY recorded_at DESC))[1]          AS "filters!",
+       COUNT(*) FILTER (WHERE reason = 'zero_hits')               AS "zero_hits!",
+       COUNT(*) FILTER (WHERE reason = 'low_position')            AS "low_positions!",
+       ARRAY_REMOVE(ARRAY_AGG(DISTINCT picked_id), NULL)          AS "picked_ids!",
+       MAX(recorded_at)                                           AS "last_seen_at!"
+FROM search_misses
+WHERE recorded_at >= $1
+GROUP BY LOWER(TRIM(query))
+ORDER BY COUNT(*) DESC, MAX(recorded_at) DESC
+LIMIT $2"#,
+            since,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(LimitedVec(reports))
+    }
+}
+
+/// A query, for which users repeatedly picked a result
+#[derive(Debug, Clone)]
+pub struct PickedQuery {
+    pub query: String,
+    pub picks: i64,
+}
+
+impl PickedQuery {
+    /// All (case-insensitive) queries, for which users picked a result at least `min_picks` times
+    #[tracing::instrument(skip(pool))]
+    pub async fn fetch_frequent(pool: &PgPool, min_picks: i64) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            PickedQuery,
+            r#"
+SELECT LOWER(TRIM(query)) AS "query!",
+       COUNT(*)           AS "picks!"
+FROM search_misses
+WHERE reason = 'low_position'
+GROUP BY LOWER(TRIM(query))
+HAVING COUNT(*) >= $1"#,
+            min_picks
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::setup::tests::PostgresTestContainer;
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn misses_are_aggregated_by_query() {
+        let pg = PostgresTestContainer::new().await;
+        let misses = [
+            SearchMiss::zero_hits("mi hs 4"),
+            SearchMiss::zero_hits("MI HS 4 "),
+            SearchMiss::low_position("mi hs 4", "5602.EG.001", 7),
+            SearchMiss::zero_hits("usage:wc in:garching"),
+        ];
+        for miss in misses {
+            miss.store(&pg.pool).await.unwrap();
+        }
+        let since = Utc::now() - chrono::Duration::hours(1);
+        let reports = MissReport::fetch(&pg.pool, since, 10).await.unwrap();
+        assert_eq!(reports.len(), 2);
+        let report = &reports.0[0];
+        assert_eq!(report.query, "mi hs 4");
+        assert_eq!(report.zero_hits, 2);
+        assert_eq!(report.low_positions, 1);
+        assert_eq!(report.picked_ids, vec!["5602.EG.001".to_string()]);
+        let report = &reports.0[1];
+        assert_eq!(report.query, "usage:wc in:garching");
+        assert_eq!(
+            report.filters,
+            r#"((parent_keywords IN ["garching"]) OR (parent_building_names IN ["garching"]) OR (campus IN ["garching"])) AND (usage IN ["wc"])"#
+        );
+        assert_eq!(report.tokens, serde_json::json!([]));
+    }
+}
@@ -106,6 +106,12 @@ If you'd like to help out or join us in this adventure, we would love to talk to
             .name("maps".to_string())
             .description(Some("API to access for map-data"))
             .build(),
+        TagBuilder::new()
+            .name("admin".to_string())
+            .description(Some(
+                "APIs for operating this server. Require an admin token",
+            ))
+            .build(),
     ]);
     openapi.external_docs = Some(
         ExternalDocsBuilder::new()
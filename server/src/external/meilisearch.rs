@@ -1,11 +1,17 @@
 use meilisearch_sdk::client::Client;
 use meilisearch_sdk::errors::Error;
 use meilisearch_sdk::indexes::Index;
-use meilisearch_sdk::search::{MultiSearchResponse, SearchQuery, Selectors};
-use serde::Deserialize;
+use meilisearch_sdk::search::{MultiSearchResponse, SearchQuery, SearchResults, Selectors};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter};
 
-use crate::routes::search::{Highlighting, Limits};
+use crate::routes::search::{Highlighting, Limits, PaginatedFacet};
+
+#[expect(
+    unused_imports,
+    reason = "has to be imported as otherwise utoipa generates incorrect code"
+)]
+use serde_json::json;
 
 #[derive(Deserialize, Default, Clone)]
 #[allow(dead_code)]
@@ -13,16 +19,44 @@ pub struct MSHit {
     ms_id: String,
     pub room_code: String,
     pub name: String,
+    #[serde(default)]
+    name_en: Option<String>,
     pub arch_name: Option<String>,
     pub r#type: String,
     pub type_common_name: String,
+    #[serde(default)]
+    type_common_name_en: Option<String>,
     pub parent_building_names: Vec<String>,
+    #[serde(default)]
+    parent_building_names_en: Vec<String>,
     parent_keywords: Vec<String>,
     pub campus: Option<String>,
     address: Option<String>,
     usage: Option<String>,
     rank: i32,
 }
+impl MSHit {
+    /// Whether this entry is located inside the given `parent`
+    pub fn is_located_in(&self, parent: &str) -> bool {
+        self.parent_keywords.contains(&parent.to_lowercase())
+    }
+    /// Whether this entry is located inside any of the given `parents`
+    pub fn is_located_in_any(&self, parents: &[String]) -> bool {
+        parents.iter().any(|parent| self.is_located_in(parent))
+    }
+    /// Replaces the german names by their english translations, where these exist
+    pub fn localise_to_english(&mut self) {
+        if let Some(name) = self.name_en.take() {
+            self.name = name;
+        }
+        if let Some(type_common_name) = self.type_common_name_en.take() {
+            self.type_common_name = type_common_name;
+        }
+        if !self.parent_building_names_en.is_empty() {
+            self.parent_building_names = std::mem::take(&mut self.parent_building_names_en);
+        }
+    }
+}
 impl Debug for MSHit {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MSHit")
@@ -37,6 +71,7 @@ struct GeoEntryFilters {
     default: String,
     rooms: String,
     buildings: String,
+    sites_buildings: String,
 }
 impl Default for GeoEntryFilters {
     fn default() -> Self {
@@ -44,6 +79,7 @@ impl Default for GeoEntryFilters {
             default: "".to_string(),
             rooms: "facet = \"room\"".to_string(),
             buildings: "facet = \"building\"".to_string(),
+            sites_buildings: "facet IN [\"site\", \"building\"]".to_string(),
         }
     }
 }
@@ -59,6 +95,9 @@ impl Debug for GeoEntryFilters {
         if !self.buildings.is_empty() {
             base.field("buildings", &self.buildings);
         }
+        if !self.sites_buildings.is_empty() {
+            base.field("sites_buildings", &self.sites_buildings);
+        }
         base.finish()
     }
 }
@@ -72,21 +111,17 @@ impl From<&String> for GeoEntryFilters {
 impl GeoEntryFilters {
     pub fn with_filter(&mut self, ms_filter: impl ToString) -> Self {
         let ms_filter = ms_filter.to_string();
-        // --
-        if self.default.is_empty() && !ms_filter.is_empty() {
-            self.default.push_str(" AND ")
-        }
-        self.default.push_str(&ms_filter);
-        // --
-        if self.buildings.is_empty() && !ms_filter.is_empty() {
-            self.buildings.push_str(" AND ")
-        }
-        self.buildings.push_str(&ms_filter);
-        // --
-        if self.rooms.is_empty() && !ms_filter.is_empty() {
-            self.rooms.push_str(" AND ")
+        for filter in [
+            &mut self.default,
+            &mut self.buildings,
+            &mut self.rooms,
+            &mut self.sites_buildings,
+        ] {
+            if !filter.is_empty() && !ms_filter.is_empty() {
+                filter.push_str(" AND ")
+            }
+            filter.push_str(&ms_filter);
         }
-        self.rooms.push_str(&ms_filter);
         self.clone()
     }
 }
@@ -99,6 +134,43 @@ pub struct GeoEntryQuery {
     highlighting: Highlighting,
     filters: GeoEntryFilters,
     sorting: Vec<String>,
+    /// facets for which the merged query returns the distribution
+    facets: Vec<&'static str>,
+    /// whether the hits include how their ranking score was computed
+    ranking_score_details: bool,
+}
+
+/// The sub-queries a [`GeoEntryQuery`] consists of
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubQueryKind {
+    /// All entries, limited by the total limit
+    Merged,
+    /// Only buildings
+    Buildings,
+    /// Only rooms
+    Rooms,
+}
+
+/// What exactly is sent to meilisearch for one of the sub-queries of a [`GeoEntryQuery`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, utoipa::ToSchema)]
+pub struct SubQuery {
+    /// Which of the sub-queries this is
+    pub kind: SubQueryKind,
+    /// The query string
+    #[schema(example = "mw 2001 mw2001")]
+    pub q: String,
+    /// The filter string
+    ///
+    /// Empty if nothing is filtered.
+    #[schema(example = "facet = \"room\" AND (usage IN [\"wc\"])")]
+    pub filter: String,
+    /// The sorting criteria
+    #[schema(example = json!(["_geoPoint(48.26,11.67):asc"]))]
+    pub sort: Vec<String>,
+    /// Maximum number of hits
+    #[schema(example = 10)]
+    pub limit: usize,
 }
 
 impl From<(&Client, String, &Limits, &Highlighting)> for GeoEntryQuery {
@@ -112,6 +184,8 @@ impl From<(&Client, String, &Limits, &Highlighting)> for GeoEntryQuery {
             highlighting: highlighting.clone(),
             filters: GeoEntryFilters::default(),
             sorting: Vec::new(),
+            facets: vec!["facet"],
+            ranking_score_details: false,
         }
     }
 }
@@ -127,6 +201,45 @@ impl GeoEntryQuery {
         self.filters.with_filter(ms_filter);
         self.clone()
     }
+    // add a facet whose distribution is returned for the merged query
+    pub fn with_facet_distribution(&mut self, facet: &'static str) -> Self {
+        if !self.facets.contains(&facet) {
+            self.facets.push(facet);
+        }
+        self.clone()
+    }
+    // include the ranking score and how it was computed in the hits
+    pub fn with_ranking_score_details(&mut self) -> Self {
+        self.ranking_score_details = true;
+        self.clone()
+    }
+    /// The sub-queries [`Self::execute`] sends to meilisearch, in the order their results are returned
+    pub fn sub_queries(&self) -> [SubQuery; 3] {
+        let sub_query = |kind, filter: &String, limit| SubQuery {
+            kind,
+            q: self.query.clone(),
+            filter: filter.clone(),
+            sort: self.sorting.clone(),
+            limit,
+        };
+        [
+            sub_query(
+                SubQueryKind::Merged,
+                &self.filters.default,
+                self.limits.total_count,
+            ),
+            sub_query(
+                SubQueryKind::Buildings,
+                &self.filters.buildings,
+                2 * self.limits.buildings_count,
+            ),
+            sub_query(
+                SubQueryKind::Rooms,
+                &self.filters.rooms,
+                self.limits.rooms_count,
+            ),
+        ]
+    }
     pub async fn execute(self) -> Result<MultiSearchResponse<MSHit>, Error> {
         let entries = self.client.index("entries");
 
@@ -164,16 +277,95 @@ impl GeoEntryQuery {
             .await
     }
 
+    /// Executes the sub-queries of all `queries` in a single multi-search round trip
+    ///
+    /// For each query, its three results (see [`Self::execute`]) are returned in the order of `queries`.
+    /// In contrast to [`Self::execute`], the ranking score of each hit is included.
+    pub async fn execute_batch(
+        client: &Client,
+        queries: &[GeoEntryQuery],
+    ) -> Result<MultiSearchResponse<MSHit>, Error> {
+        let entries = client.index("entries");
+        let sortings = queries
+            .iter()
+            .map(|q| q.sorting.iter().map(String::as_str).collect::<Vec<&str>>())
+            .collect::<Vec<Vec<&str>>>();
+
+        let mut multi_search = client.multi_search();
+        for (query, sorting) in queries.iter().zip(&sortings) {
+            multi_search
+                .with_search_query(
+                    query
+                        .merged_query(&entries, &query.query)
+                        .with_show_ranking_score(true)
+                        .with_sort(sorting)
+                        .build(),
+                )
+                .with_search_query(
+                    query
+                        .buildings_query(&entries, &query.query)
+                        .with_show_ranking_score(true)
+                        .with_sort(sorting)
+                        .build(),
+                )
+                .with_search_query(
+                    query
+                        .rooms_query(&entries, &query.query)
+                        .with_show_ranking_score(true)
+                        .with_sort(sorting)
+                        .build(),
+                );
+        }
+        multi_search.execute::<MSHit>().await
+    }
+
+    /// Executes only a single query for one page of the given `facet`
+    ///
+    /// In contrast to [`Self::execute`], the results are not supposed to be merged.
+    pub async fn execute_page(
+        self,
+        facet: PaginatedFacet,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchResults<MSHit>, Error> {
+        let entries = self.client.index("entries");
+        let sorting = self
+            .sorting
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        let filter = match facet {
+            PaginatedFacet::SitesBuildings => &self.filters.sites_buildings,
+            PaginatedFacet::Rooms => &self.filters.rooms,
+        };
+        self.common_query(&entries)
+            .with_query(&self.query)
+            .with_offset(offset)
+            .with_limit(limit)
+            .with_filter(filter)
+            .with_facets(Selectors::Some(self.facets.as_slice()))
+            .with_sort(&sorting)
+            .execute::<MSHit>()
+            .await
+    }
+
     fn common_query<'b: 'a, 'a>(
         &'b self,
         entries: &'a Index,
     ) -> SearchQuery<'a, meilisearch_sdk::DefaultHttpClient> {
-        SearchQuery::new(entries)
+        let mut s = SearchQuery::new(entries)
             .with_facets(Selectors::Some(&["facet"]))
             .with_highlight_pre_tag(&self.highlighting.pre)
             .with_highlight_post_tag(&self.highlighting.post)
-            .with_attributes_to_highlight(Selectors::Some(&["name"]))
-            .build()
+            .with_attributes_to_highlight(Selectors::Some(&["name", "name_en"]))
+            .build();
+        if self.ranking_score_details {
+            s = s
+                .with_show_ranking_score(true)
+                .with_show_ranking_score_details(true)
+                .build();
+        }
+        s
     }
 
     fn merged_query<'a>(
@@ -185,6 +377,7 @@ impl GeoEntryQuery {
             .common_query(entries)
             .with_query(query)
             .with_limit(self.limits.total_count)
+            .with_facets(Selectors::Some(self.facets.as_slice()))
             .build();
         if !self.filters.default.is_empty() {
             s = s.with_filter(&self.filters.default).build();
@@ -227,6 +420,92 @@ impl Debug for GeoEntryQuery {
         if !self.sorting.is_empty() {
             base.field("sorting", &self.sorting);
         }
+        if self.facets.len() > 1 {
+            base.field("facets", &self.facets);
+        }
+        if self.ranking_score_details {
+            base.field("ranking_score_details", &self.ranking_score_details);
+        }
         base.finish()
     }
 }
+
+/// A single, lightweight query for entries.
+///
+/// Intended for suggesting completions while typing, where [`GeoEntryQuery`] is too heavy.
+#[tracing::instrument(skip(client))]
+pub async fn suggest_entries(
+    client: &Client,
+    query: &str,
+    filter: &str,
+    sorting: &[String],
+    limit: usize,
+) -> Result<SearchResults<MSHit>, Error> {
+    let entries = client.index("entries");
+    let sorting = sorting.iter().map(String::as_str).collect::<Vec<&str>>();
+    let mut search = SearchQuery::new(&entries);
+    search
+        .with_query(query)
+        .with_limit(limit)
+        .with_sort(&sorting);
+    if !filter.is_empty() {
+        search.with_filter(filter);
+    }
+    search.execute::<MSHit>().await
+}
+
+/// Values of the filterable `facet` starting with `prefix`, ordered by how often they occur
+#[tracing::instrument(skip(client))]
+pub async fn suggest_facet_values(
+    client: &Client,
+    facet: &str,
+    prefix: &str,
+    limit: usize,
+) -> Result<Vec<String>, Error> {
+    let entries = client.index("entries");
+    let response = entries
+        .facet_search(facet)
+        .with_facet_query(prefix)
+        .execute()
+        .await?;
+    Ok(response
+        .facet_hits
+        .into_iter()
+        .take(limit)
+        .map(|hit| hit.value)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn filters_are_combined() {
+        let mut filters = GeoEntryFilters::default();
+        filters.with_filter("(usage IN [\"wc\"])");
+        filters.with_filter("(campus IN [\"garching\"])");
+        assert_eq!(
+            filters.default,
+            "(usage IN [\"wc\"]) AND (campus IN [\"garching\"])"
+        );
+        assert_eq!(
+            filters.rooms,
+            "facet = \"room\" AND (usage IN [\"wc\"]) AND (campus IN [\"garching\"])"
+        );
+        assert_eq!(
+            filters.sites_buildings,
+            "facet IN [\"site\", \"building\"] AND (usage IN [\"wc\"]) AND (campus IN [\"garching\"])"
+        );
+    }
+
+    #[test]
+    fn empty_filters_are_ignored() {
+        let mut filters = GeoEntryFilters::default();
+        filters.with_filter("");
+        assert_eq!(filters.default, "");
+        assert_eq!(filters.buildings, "facet = \"building\"");
+    }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-#[derive(Deserialize, Serialize, Copy, Clone, Debug, Eq, PartialEq, Default, utoipa::ToSchema)]
+#[derive(
+    Deserialize, Serialize, Copy, Clone, Debug, Eq, PartialEq, Hash, Default, utoipa::ToSchema,
+)]
 #[serde(rename_all = "snake_case")]
 enum LanguageOptions {
     #[default]
@@ -17,6 +19,7 @@ enum LanguageOptions {
     Debug,
     Eq,
     PartialEq,
+    Hash,
     Default,
     utoipa::IntoParams,
     utoipa::ToSchema,
@@ -40,6 +40,8 @@ pub struct AppData {
     /// necessary, as otherwise we could return empty results during initialisation
     meilisearch_initialised: Arc<RwLock<()>>,
     valhalla: external::valhalla::ValhallaWrapper,
+    /// token admin requests have to carry, if admin endpoints are configured
+    admin_token: Option<String>,
 }
 
 impl AppData {
@@ -58,6 +60,7 @@ impl From<PgPool> for AppData {
             pool,
             meilisearch_initialised: Arc::new(Default::default()),
             valhalla: external::valhalla::ValhallaWrapper::default(),
+            admin_token: admin::admin_token(),
         }
     }
 }
@@ -172,13 +175,13 @@ async fn run_maintenance_work(
 ) {
     if std::env::var("SKIP_MS_SETUP") != Ok("true".to_string()) {
         let _ = debug_span!("updating meilisearch data").enter();
-        let _ = meilisearch_initialised.write().await;
+        let _initialising = meilisearch_initialised.write().await;
         initialisation_started.wait().await;
         let ms_url =
             std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
         let client = Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok()).unwrap();
         setup::meilisearch::setup(&client).await.unwrap();
-        setup::meilisearch::load_data(&client).await.unwrap();
+        setup::meilisearch::reindex(&client).await.unwrap();
     } else {
         info!("skipping the database setup as SKIP_MS_SETUP=true");
         initialisation_started.wait().await;
@@ -222,6 +225,13 @@ async fn run() -> anyhow::Result<()> {
         .finish()
         .expect("Invalid configuration of the governor");
     let recorded_tokens = web::Data::new(feedback::tokens::RecordedTokens::default());
+    // search endpoints, which are expensive to serve or write to the database
+    let search_ratelimit = GovernorConfigBuilder::default()
+        .key_extractor(GlobalKeyExtractor)
+        .seconds_per_request(1) // replenish new token every .. seconds
+        .burst_size(30)
+        .finish()
+        .expect("Invalid configuration of the governor");
 
     info!("running the server");
     HttpServer::new(move || {
@@ -246,20 +256,37 @@ async fn run() -> anyhow::Result<()> {
                 .app_data(recorded_tokens.clone())
                 .service(health_status_handler)
                 .service(calendar::calendar_handler)
+                .service(calendar::free_rooms::free_rooms_handler)
+                .service(calendar::ics::ics_handler)
+                .service(calendar::availability::availability_handler)
+                .service(calendar::changes::changes_handler)
                 .service(maps::indoor::list_indoor_maps)
                 .service(maps::indoor::get_indoor_map)
                 .service(maps::route::route_handler)
                 .service(search::search_handler)
+                .service(search::suggest_handler)
                 .service(locations::details::get_handler)
                 .service(locations::nearby::nearby_handler)
                 .service(locations::preview::maps_handler)
                 .service(feedback::post_feedback::send_feedback)
                 .service(feedback::proposed_edits::propose_edits)
+                .service(admin::search_misses::search_misses_handler)
+                .service(admin::search_reindex::search_reindex_handler)
                 .service(
                     scope("/api/feedback/get_token")
                         .wrap(actix_governor::Governor::new(&feedback_ratelimit))
                         .service(feedback::tokens::get_token),
                 )
+                .service(
+                    scope("/api/search/batch")
+                        .wrap(actix_governor::Governor::new(&search_ratelimit))
+                        .service(search::batch_search_handler),
+                )
+                .service(
+                    scope("/api/search/picked")
+                        .wrap(actix_governor::Governor::new(&search_ratelimit))
+                        .service(search::picked_handler),
+                )
                 .service(openapi_doc),
         )
     })
@@ -279,8 +306,11 @@ fn build_metrics() -> PrometheusMetrics {
             .unwrap_or_else(|| "development")
             .to_string(),
     )]);
+    let registry = prometheus::Registry::new();
+    search::register_metrics(&registry).expect("specified metrics are valid");
     PrometheusMetricsBuilder::new("navigatum_api")
         .endpoint("/api/metrics")
+        .registry(registry)
         .const_labels(labels)
         .build()
         .expect("specified metrics are valid")
@@ -0,0 +1,160 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{HttpRequest, HttpResponse};
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::AppData;
+
+pub mod search_misses;
+pub mod search_reindex;
+
+/// The token admin requests have to carry, if admin endpoints are configured
+pub(crate) fn admin_token() -> Option<String> {
+    std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// Checks that the request carries the `Authorization: Bearer <ADMIN_TOKEN>` header
+///
+/// Returns the response to send instead, if the request is not authorised.
+#[tracing::instrument(skip(req, data))]
+pub fn validate(req: &HttpRequest, data: &AppData) -> Option<HttpResponse> {
+    validate_token(req, data.admin_token.as_deref())
+}
+
+fn validate_token(req: &HttpRequest, expected: Option<&str>) -> Option<HttpResponse> {
+    let Some(expected) = expected else {
+        return Some(
+            HttpResponse::ServiceUnavailable()
+                .content_type("text/plain")
+                .body("Admin endpoints are currently not configured on this server."),
+        );
+    };
+    let provided = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        None => Some(
+            HttpResponse::Unauthorized()
+                .content_type("text/plain")
+                .body("Missing bearer token"),
+        ),
+        // compared in constant time to not leak how much of the token was guessed correctly
+        Some(token) if !bool::from(token.as_bytes().ct_eq(expected.as_bytes())) => {
+            warn!("admin request with an invalid token");
+            Some(
+                HttpResponse::Forbidden()
+                    .content_type("text/plain")
+                    .body("Invalid token"),
+            )
+        }
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn status_of(req: &HttpRequest, expected: Option<&str>) -> Option<u16> {
+        validate_token(req, expected).map(|res| res.status().as_u16())
+    }
+
+    #[test]
+    fn requests_are_rejected_without_a_configured_token() {
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert_eq!(status_of(&req, None), Some(503));
+    }
+
+    #[test]
+    fn the_bearer_token_has_to_match() {
+        let token = Some("secret");
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(status_of(&req, token), Some(401));
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "secret"))
+            .to_http_request();
+        assert_eq!(status_of(&req, token), Some(401));
+        for provided in ["Bearer wrong", "Bearer secre", "Bearer secrets"] {
+            let req = TestRequest::default()
+                .insert_header((AUTHORIZATION, provided))
+                .to_http_request();
+            assert_eq!(status_of(&req, token), Some(403), "{provided}");
+        }
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert_eq!(status_of(&req, token), None);
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use actix_web::{test, web, App};
+    use pretty_assertions::assert_eq;
+
+    use crate::db::search_misses::SearchMiss;
+    use crate::setup::tests::PostgresTestContainer;
+    use crate::AppData;
+
+    const TOKEN: &str = "test-admin-token";
+
+    #[actix_web::test]
+    #[tracing_test::traced_test]
+    async fn admin_endpoints_require_the_token() {
+        let pg = PostgresTestContainer::new().await;
+        SearchMiss::zero_hits("mi hs 4")
+            .store(&pg.pool)
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData {
+                    admin_token: Some(TOKEN.to_string()),
+                    ..AppData::from(pg.pool.clone())
+                }))
+                .service(super::search_misses::search_misses_handler)
+                .service(super::search_reindex::search_reindex_handler),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/admin/search/misses")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/api/admin/search/misses")
+            .insert_header(("Authorization", "Bearer wrong"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+        let req = test::TestRequest::get()
+            .uri("/api/admin/search/misses")
+            .insert_header(("Authorization", format!("Bearer {TOKEN}")))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let reports: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(reports[0]["query"], "mi hs 4");
+        assert_eq!(reports[0]["zero_hits"], 1);
+
+        let req = test::TestRequest::post()
+            .uri("/api/admin/search/reindex")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        // a reindex, which is already running, is not started a second time
+        let _running = crate::setup::meilisearch::start_reindexing().await;
+        let req = test::TestRequest::post()
+            .uri("/api/admin/search/reindex")
+            .insert_header(("Authorization", format!("Bearer {TOKEN}")))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 409);
+    }
+}
@@ -0,0 +1,114 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::db::search_misses::MissReport;
+use crate::AppData;
+
+#[expect(
+    unused_imports,
+    reason = "has to be imported as otherwise utoipa generates incorrect code"
+)]
+use serde_json::json;
+
+#[derive(Deserialize, Debug, utoipa::IntoParams, utoipa::ToSchema)]
+struct MissesQueryArgs {
+    /// Only include misses recorded after this point in time.
+    ///
+    /// Defaults to the last 30 days.
+    #[schema(examples("2039-01-19T03:14:07+01:00", "2042-01-07T00:00:00 UTC"))]
+    since: Option<DateTime<Utc>>,
+    /// Maximum number of queries to return.
+    ///
+    /// Clamped to `1`..`1000`.
+    #[schema(default = 100, minimum = 1, maximum = 1000)]
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+struct MissReportResponse {
+    /// The (lowercased) query, which was missed
+    #[schema(example = "mi hs 4")]
+    query: String,
+    /// The text tokens of the query, as the search understood them the last time it was missed
+    #[schema(value_type = Object, example = json!([{"text": "mi"}, {"text": "hs"}, {"text": "4"}]))]
+    tokens: serde_json::Value,
+    /// The filters of the query, as passed to meilisearch the last time it was missed
+    #[schema(example = "(usage IN [\"wc\"])")]
+    filters: String,
+    /// How often the query did not return any results
+    #[schema(example = 12)]
+    zero_hits: i64,
+    /// How often a user had to pick a result far down the list
+    #[schema(example = 3)]
+    low_positions: i64,
+    /// The ids users picked far down the list.
+    ///
+    /// If this is non-empty, a synonym or alias is likely missing.
+    #[schema(example = json!(["5602.EG.001"]))]
+    picked_ids: Vec<String>,
+    /// When this query was last missed
+    #[schema(example = "2039-01-19T03:14:07+01:00")]
+    last_seen_at: DateTime<Utc>,
+}
+
+impl From<MissReport> for MissReportResponse {
+    fn from(report: MissReport) -> Self {
+        Self {
+            query: report.query,
+            tokens: report.tokens,
+            filters: report.filters,
+            zero_hits: report.zero_hits,
+            low_positions: report.low_positions,
+            picked_ids: report.picked_ids,
+            last_seen_at: report.last_seen_at,
+        }
+    }
+}
+
+/// Report of missed search queries
+///
+/// Lists the queries which most often did not return any results, or where users had to pick a result far down the list.
+/// This is intended to find missing synonyms and aliases.
+///
+/// Requires the `Authorization: Bearer <ADMIN_TOKEN>` header.
+#[utoipa::path(
+    tags=["admin"],
+    params(MissesQueryArgs),
+    responses(
+        (status = 200, description = "The **most common misses**, ordered by how often they were missed", body = Vec<MissReportResponse>, content_type = "application/json"),
+        (status = 400, description= "**Bad Request.** Not all fields in the body are present as defined above", body = String, content_type = "text/plain", example = "Query deserialize error: premature end of input"),
+        (status = 401, description = "**Unauthorized.** No bearer token was provided", body = String, content_type = "text/plain", example = "Missing bearer token"),
+        (status = 403, description = "**Forbidden.** The bearer token is invalid", body = String, content_type = "text/plain", example = "Invalid token"),
+        (status = 503, description = "**Service unavailable.** Admin endpoints are not configured on this server", body = String, content_type = "text/plain", example = "Admin endpoints are currently not configured on this server."),
+    )
+)]
+#[get("/api/admin/search/misses")]
+pub async fn search_misses_handler(
+    req: HttpRequest,
+    web::Query(args): web::Query<MissesQueryArgs>,
+    data: web::Data<AppData>,
+) -> HttpResponse {
+    if let Some(response) = super::validate(&req, &data) {
+        return response;
+    }
+    let since = args
+        .since
+        .unwrap_or_else(|| Utc::now() - chrono::Duration::days(30));
+    let limit = args.limit.unwrap_or(100).clamp(1, 1_000);
+    match MissReport::fetch(&data.pool, since, limit).await {
+        Ok(reports) => HttpResponse::Ok().json(
+            reports
+                .into_iter()
+                .map(MissReportResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => {
+            error!(error = ?e, "could not fetch the search misses");
+            HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get the search misses, please try again later")
+        }
+    }
+}
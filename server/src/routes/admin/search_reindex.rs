@@ -0,0 +1,57 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use meilisearch_sdk::client::Client;
+use tracing::{error, info};
+
+use crate::AppData;
+
+/// Reindex the search
+///
+/// Loads the current data into a new search index and swaps it with the live one once it is complete.
+/// Search is not affected while reindexing.
+/// If the new index is implausible (e.g. has far fewer entries than the live one), the live index is kept.
+///
+/// Reindexing runs in the background and takes a few minutes.
+/// Its outcome is only logged.
+///
+/// Requires the `Authorization: Bearer <ADMIN_TOKEN>` header.
+#[utoipa::path(
+    tags=["admin"],
+    responses(
+        (status = 202, description = "**Accepted.** Reindexing was started", body = String, content_type = "text/plain", example = "Reindexing started"),
+        (status = 401, description = "**Unauthorized.** No bearer token was provided", body = String, content_type = "text/plain", example = "Missing bearer token"),
+        (status = 403, description = "**Forbidden.** The bearer token is invalid", body = String, content_type = "text/plain", example = "Invalid token"),
+        (status = 409, description = "**Conflict.** Reindexing is already running", body = String, content_type = "text/plain", example = "Reindexing is already running"),
+        (status = 503, description = "**Service unavailable.** Admin endpoints are not configured on this server", body = String, content_type = "text/plain", example = "Admin endpoints are currently not configured on this server."),
+    )
+)]
+#[post("/api/admin/search/reindex")]
+pub async fn search_reindex_handler(req: HttpRequest, data: web::Data<AppData>) -> HttpResponse {
+    if let Some(response) = super::validate(&req, &data) {
+        return response;
+    }
+    // taken here, so that concurrent requests cannot both pass this check
+    let Some(permit) = crate::setup::meilisearch::try_start_reindexing() else {
+        return HttpResponse::Conflict()
+            .content_type("text/plain")
+            .body("Reindexing is already running");
+    };
+    let ms_url = std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
+    let client = match Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok()) {
+        Ok(client) => client,
+        Err(e) => {
+            error!(error = ?e, "Failed to create a meilisearch client");
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("Cannot reindex, please try again later");
+        }
+    };
+    tokio::spawn(async move {
+        match crate::setup::meilisearch::reindex_with(&client, permit).await {
+            Ok(report) => info!(?report, "reindexing requested via the api succeeded"),
+            Err(e) => error!(error = ?e, "reindexing requested via the api failed"),
+        }
+    });
+    HttpResponse::Accepted()
+        .content_type("text/plain")
+        .body("Reindexing started")
+}
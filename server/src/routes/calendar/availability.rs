@@ -0,0 +1,223 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::{fetch_calendar_location, validate_window};
+use crate::db::calendar::Event;
+
+/// Calendars are scraped roughly every hour.
+/// Anything much older than this means that scraping this room failed repeatedly.
+const MAX_SCRAPE_AGE: Duration = Duration::hours(3);
+const DEFAULT_MIN_SLOT_MINUTES: u32 = 15;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+struct AvailabilityPathParams {
+    /// ID of the room
+    #[param(example = "5602.EG.001")]
+    id: String,
+}
+
+#[derive(Deserialize, Debug, utoipa::IntoParams)]
+struct AvailabilityQueryArgs {
+    /// The start of the window to compute free slots for
+    #[param(example = "2039-01-19T03:14:07+01:00")]
+    start_after: DateTime<Utc>,
+    /// The end of the window to compute free slots for
+    ///
+    /// The window may be at most 31 days long.
+    #[param(example = "2039-01-20T03:14:07+01:00")]
+    end_before: DateTime<Utc>,
+    /// Free slots shorter than this (in minutes) are omitted
+    #[param(default = 15, minimum = 1)]
+    min_slot_minutes: Option<u32>,
+}
+
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+struct AvailabilityResponse {
+    /// The slots, in which the room is free, the earliest first
+    free: Vec<FreeSlotResponse>,
+    /// last time the calendar was scraped for this room
+    #[schema(examples("2039-01-19T03:14:07+01:00"))]
+    last_calendar_scrape_at: DateTime<Utc>,
+    /// If the last scrape is so old, that the calendar (and thus the free slots) might be outdated
+    stale: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, utoipa::ToSchema)]
+struct FreeSlotResponse {
+    /// start of the free slot
+    #[schema(examples("2039-01-19T08:00:00Z"))]
+    start_at: DateTime<Utc>,
+    /// end of the free slot
+    #[schema(examples("2039-01-19T10:00:00Z"))]
+    end_at: DateTime<Utc>,
+}
+
+/// Get the availability of a room
+///
+/// Computes the slots in the window `start_after`..`end_before` in which the room `id` has no calendar entries.
+/// Every kind of entry (including `barred` ones) makes the room unavailable.
+///
+/// If the calendar of the room was not scraped for a while, the response is flagged as `stale`.
+#[utoipa::path(
+    tags=["calendar"],
+    params(AvailabilityPathParams, AvailabilityQueryArgs),
+    responses(
+        (status = 200, description = "**Free slots** of the room in the requested window", body = AvailabilityResponse, content_type = "application/json"),
+        (status = 400, description = "**Bad Request.** The window is empty or too long", body = String, content_type = "text/plain", example = "The window may be at most 31 days long"),
+        (status = 404, description = "**Not found.** The requested location does not exist or does not have a calendar", body = String, content_type = "text/plain", example = "Not found"),
+        (status = 503, description = "**Not Ready.** please retry later", body = String, content_type = "text/plain", example = "Waiting for first sync with TUMonline"),
+    )
+)]
+#[get("/api/calendar/{id}/availability")]
+pub async fn availability_handler(
+    params: web::Path<AvailabilityPathParams>,
+    web::Query(args): web::Query<AvailabilityQueryArgs>,
+    data: web::Data<crate::AppData>,
+) -> HttpResponse {
+    if let Err(e) = validate_window(&args.start_after, &args.end_before) {
+        return e;
+    }
+    let min_slot = args
+        .min_slot_minutes
+        .unwrap_or(DEFAULT_MIN_SLOT_MINUTES)
+        .max(1);
+    let min_slot = Duration::minutes(i64::from(min_slot));
+    let location = match fetch_calendar_location(&data.pool, &params.id).await {
+        Ok(location) => location,
+        Err(e) => return e,
+    };
+    let events = Event::fetch_overlapping(
+        &data.pool,
+        &location.key,
+        &args.start_after,
+        &args.end_before,
+    )
+    .await;
+    let events = match events {
+        Ok(events) => events,
+        Err(e) => {
+            error!(error = ?e, key = location.key, "could not get entries from the db");
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get calendar entries, please try again later");
+        }
+    };
+    let last_calendar_scrape_at = location
+        .last_calendar_scrape_at
+        .expect("we filterd for last_calendar_scrape_at in the step beforehand");
+    let busy = events
+        .iter()
+        .map(|e| (e.start_at, e.end_at))
+        .collect::<Vec<_>>();
+    let response = AvailabilityResponse {
+        free: free_slots(&busy, args.start_after, args.end_before, min_slot),
+        last_calendar_scrape_at,
+        stale: Utc::now() - last_calendar_scrape_at > MAX_SCRAPE_AGE,
+    };
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::MaxAge(5 * 60), // valid for 5min
+            CacheDirective::Public,
+        ]))
+        .json(response)
+}
+
+/// The gaps of at least `min_slot` between the `busy` intervals inside `start`..`end`
+///
+/// `busy` may be unsorted and overlapping.
+fn free_slots(
+    busy: &[(DateTime<Utc>, DateTime<Utc>)],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    min_slot: Duration,
+) -> Vec<FreeSlotResponse> {
+    let mut busy = busy.to_vec();
+    busy.sort_unstable();
+    let mut free = Vec::new();
+    let mut free_since = start;
+    for (busy_start, busy_end) in busy {
+        let busy_start = busy_start.min(end);
+        if busy_start - free_since >= min_slot {
+            free.push(FreeSlotResponse {
+                start_at: free_since,
+                end_at: busy_start,
+            });
+        }
+        free_since = free_since.max(busy_end);
+    }
+    if end - free_since >= min_slot {
+        free.push(FreeSlotResponse {
+            start_at: free_since,
+            end_at: end,
+        });
+    }
+    free
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn at(hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 7, 1, hour, min, 0).unwrap()
+    }
+    fn slot(start: DateTime<Utc>, end: DateTime<Utc>) -> FreeSlotResponse {
+        FreeSlotResponse {
+            start_at: start,
+            end_at: end,
+        }
+    }
+
+    #[test]
+    fn without_events_the_whole_window_is_free() {
+        let free = free_slots(&[], at(8, 0), at(18, 0), Duration::minutes(15));
+        assert_eq!(free, vec![slot(at(8, 0), at(18, 0))]);
+    }
+
+    #[test]
+    fn gaps_between_events_are_free() {
+        let busy = [(at(12, 0), at(14, 0)), (at(8, 0), at(10, 0))];
+        let free = free_slots(&busy, at(8, 0), at(18, 0), Duration::minutes(15));
+        assert_eq!(
+            free,
+            vec![slot(at(10, 0), at(12, 0)), slot(at(14, 0), at(18, 0))]
+        );
+    }
+
+    #[test]
+    fn overlapping_events_and_events_outside_the_window() {
+        let busy = [
+            (at(6, 0), at(9, 0)),
+            (at(10, 0), at(13, 0)),
+            (at(11, 0), at(12, 0)),
+            (at(17, 0), at(20, 0)),
+        ];
+        let free = free_slots(&busy, at(8, 0), at(18, 0), Duration::minutes(15));
+        assert_eq!(
+            free,
+            vec![slot(at(9, 0), at(10, 0)), slot(at(13, 0), at(17, 0))]
+        );
+    }
+
+    #[test]
+    fn short_slots_are_omitted() {
+        let busy = [(at(8, 10), at(10, 0)), (at(10, 10), at(17, 30))];
+        let free = free_slots(&busy, at(8, 0), at(18, 0), Duration::minutes(15));
+        assert_eq!(free, vec![slot(at(17, 30), at(18, 0))]);
+        let free = free_slots(&busy, at(8, 0), at(18, 0), Duration::minutes(10));
+        assert_eq!(
+            free,
+            vec![
+                slot(at(8, 0), at(8, 10)),
+                slot(at(10, 0), at(10, 10)),
+                slot(at(17, 30), at(18, 0))
+            ]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::fetch_calendar_location;
+use crate::db::calendar_changes::{EventChange, PreviousSlot};
+
+/// How far back changes are listed, if `since` is not given
+const DEFAULT_HISTORY: Duration = Duration::days(7);
+
+#[derive(Deserialize, utoipa::IntoParams)]
+struct ChangesPathParams {
+    /// ID of the room
+    #[param(example = "5602.EG.001")]
+    id: String,
+}
+
+#[derive(Deserialize, Debug, utoipa::IntoParams)]
+struct ChangesQueryArgs {
+    /// Only list changes after this time.
+    ///
+    /// Defaults to the last 7 days.
+    #[param(example = "2039-01-19T03:14:07+01:00")]
+    since: Option<DateTime<Utc>>,
+}
+
+/// Get the changes of a calendar
+///
+/// Lists how the calendar entries of the room `id` were created, moved (to a different time or room) or cancelled.
+/// Entries moved out of the room are included.
+///
+/// Changes are noticed while syncing with TUMonline (roughly every hour) and are listed the oldest first.
+#[utoipa::path(
+    tags=["calendar"],
+    params(ChangesPathParams, ChangesQueryArgs),
+    responses(
+        (status = 200, description = "**Changes of the calendar** since `since`", body = Vec<EventChangeResponse>, content_type = "application/json"),
+        (status = 404, description = "**Not found.** The requested location does not exist or does not have a calendar", body = String, content_type = "text/plain", example = "Not found"),
+        (status = 503, description = "**Not Ready.** please retry later", body = String, content_type = "text/plain", example = "Waiting for first sync with TUMonline"),
+    )
+)]
+#[get("/api/calendar/{id}/changes")]
+pub async fn changes_handler(
+    params: web::Path<ChangesPathParams>,
+    web::Query(args): web::Query<ChangesQueryArgs>,
+    data: web::Data<crate::AppData>,
+) -> HttpResponse {
+    let since = args.since.unwrap_or_else(|| Utc::now() - DEFAULT_HISTORY);
+    let location = match fetch_calendar_location(&data.pool, &params.id).await {
+        Ok(location) => location,
+        Err(e) => return e,
+    };
+    let changes = match EventChange::fetch_since(&data.pool, &location.key, &since).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!(error = ?e, key = location.key, "could not get the changes from the db");
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get calendar changes, please try again later");
+        }
+    };
+    let changes = changes
+        .into_iter()
+        .map(EventChangeResponse::from)
+        .collect::<Vec<_>>();
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::MaxAge(5 * 60), // valid for 5min
+            CacheDirective::Public,
+        ]))
+        .json(changes)
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+struct EventChangeResponse {
+    /// ID of the calendar entry used in TUMonline internally
+    #[schema(examples(6424))]
+    id: i32,
+    kind: ChangeKindResponse,
+    /// Structured, globaly unique room code, where the entry takes place after the change
+    ///
+    /// For cancelled entries, this is where the entry would have taken place.
+    #[schema(examples("5602.EG.001", "5121.EG.003"))]
+    room_code: String,
+    /// start of the entry after the change
+    #[schema(examples("2018-01-01T00:00:00"))]
+    start_at: DateTime<Utc>,
+    /// end of the entry after the change
+    #[schema(examples("2019-01-01T00:00:00"))]
+    end_at: DateTime<Utc>,
+    /// German title of the Entry
+    #[schema(examples("Quantenteleportation"))]
+    title_de: String,
+    /// English title of the Entry
+    #[schema(examples("Quantum teleportation"))]
+    title_en: String,
+    /// Where and when the entry took place before it was moved
+    ///
+    /// Only present for `moved` entries.
+    previous: Option<PreviousSlotResponse>,
+    /// When the change was noticed
+    #[schema(examples("2039-01-19T03:14:07+01:00"))]
+    changed_at: DateTime<Utc>,
+}
+impl From<EventChange> for EventChangeResponse {
+    fn from(value: EventChange) -> Self {
+        EventChangeResponse {
+            id: value.event_id,
+            kind: ChangeKindResponse::from(value.kind),
+            room_code: value.room_code,
+            start_at: value.start_at,
+            end_at: value.end_at,
+            title_de: value.title_de,
+            title_en: value.title_en,
+            previous: value.previous.map(PreviousSlotResponse::from),
+            changed_at: value.changed_at,
+        }
+    }
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+struct PreviousSlotResponse {
+    /// Structured, globaly unique room code, where the entry took place before it was moved
+    #[schema(examples("5602.EG.002"))]
+    room_code: String,
+    /// start of the entry before it was moved
+    #[schema(examples("2018-01-01T00:00:00"))]
+    start_at: DateTime<Utc>,
+    /// end of the entry before it was moved
+    #[schema(examples("2019-01-01T00:00:00"))]
+    end_at: DateTime<Utc>,
+}
+impl From<PreviousSlot> for PreviousSlotResponse {
+    fn from(value: PreviousSlot) -> Self {
+        PreviousSlotResponse {
+            room_code: value.room_code,
+            start_at: value.start_at,
+            end_at: value.end_at,
+        }
+    }
+}
+
+/// How the entry changed
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ChangeKindResponse {
+    Created,
+    /// The entry now takes place at a different time or in a different room
+    Moved,
+    Cancelled,
+}
+impl From<String> for ChangeKindResponse {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "created" => ChangeKindResponse::Created,
+            "moved" => ChangeKindResponse::Moved,
+            _ => ChangeKindResponse::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use actix_web::{test, App};
+    use chrono::DurationRound;
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::db::calendar::{Event, EventType};
+    use crate::limited::vec::LimitedVec;
+    use crate::setup::tests::PostgresTestContainer;
+    use crate::AppData;
+
+    const ROOM: &str = "5602.EG.001";
+
+    async fn sync(pool: &sqlx::PgPool, events: &[(i32, DateTime<Utc>)]) {
+        let events = events
+            .iter()
+            .map(|(id, start_at)| Event {
+                id: *id,
+                room_code: ROOM.into(),
+                start_at: *start_at,
+                end_at: *start_at + Duration::hours(1),
+                title_de: "Quantenteleportation".into(),
+                title_en: "Quantum teleportation".into(),
+                stp_type: None,
+                entry_type: EventType::Lecture.to_string(),
+                detailed_entry_type: "Abhaltung".into(),
+            })
+            .collect();
+        Event::store_all(pool, LimitedVec(events), ROOM)
+            .await
+            .unwrap();
+    }
+
+    async fn run_testcase(resp: actix_web::dev::ServiceResponse) -> (u16, Value) {
+        let status = resp.status().as_u16();
+        let body = test::read_body(resp).await;
+        // errors are returned as plain text
+        let body = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+        (status, body)
+    }
+
+    #[actix_web::test]
+    async fn test_changes_get() {
+        let pg = PostgresTestContainer::new().await;
+        let data = json!({"name": ROOM, "type": "room", "type_common_name": "Hörsaal", "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"}, "props": {"calendar_url": "https://campus.tum.de/5602.EG.001"}});
+        for lang in ["de", "en"] {
+            let query = format!(
+                "INSERT INTO {lang}(key,data,last_calendar_scrape_at) VALUES ($1,$2,NOW())"
+            );
+            sqlx::query(&query)
+                .bind(ROOM)
+                .bind(&data)
+                .execute(&pg.pool)
+                .await
+                .unwrap();
+        }
+        // cancellations are only recorded for events, which did not yet end
+        let tomorrow = Utc::now().duration_trunc(Duration::days(1)).unwrap() + Duration::days(1);
+        let at = |hour| tomorrow + Duration::hours(hour);
+        sync(&pg.pool, &[(1, at(8)), (2, at(10))]).await;
+        // 1 is cancelled and 2 is moved
+        sync(&pg.pool, &[(2, at(14))]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData::from(pg.pool.clone())))
+                .service(changes_handler),
+        )
+        .await;
+        {
+            // unknown room
+            let req = test::TestRequest::get()
+                .uri("/api/calendar/5602.EG.999/changes")
+                .to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 404);
+            assert_eq!(actual, json!("Not found"));
+        }
+        {
+            // changes of the last 7 days
+            let req = test::TestRequest::get()
+                .uri(&format!("/api/calendar/{ROOM}/changes"))
+                .to_request();
+            let (status, mut actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            // when the changes were noticed is not deterministic
+            for change in actual.as_array_mut().unwrap() {
+                assert!(change
+                    .as_object_mut()
+                    .unwrap()
+                    .remove("changed_at")
+                    .is_some());
+            }
+            assert_eq!(
+                actual,
+                json!([
+                    {
+                        "id": 2,
+                        "kind": "moved",
+                        "room_code": ROOM,
+                        "start_at": at(14),
+                        "end_at": at(15),
+                        "title_de": "Quantenteleportation",
+                        "title_en": "Quantum teleportation",
+                        "previous": {"room_code": ROOM, "start_at": at(10), "end_at": at(11)},
+                    },
+                    {
+                        "id": 1,
+                        "kind": "cancelled",
+                        "room_code": ROOM,
+                        "start_at": at(8),
+                        "end_at": at(9),
+                        "title_de": "Quantenteleportation",
+                        "title_en": "Quantum teleportation",
+                        "previous": null,
+                    },
+                ])
+            );
+        }
+        {
+            // no changes since then
+            let since = (Utc::now() + Duration::minutes(1))
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            let req = test::TestRequest::get()
+                .uri(&format!("/api/calendar/{ROOM}/changes?since={since}"))
+                .to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(actual, json!([]));
+        }
+    }
+}
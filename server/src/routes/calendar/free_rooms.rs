@@ -0,0 +1,361 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::error;
+
+use super::{validate_window, CalendarLocationResponse};
+use crate::db::calendar::CalendarLocation;
+use crate::localisation;
+
+#[derive(Deserialize, Debug, utoipa::IntoParams)]
+struct FreeRoomsQueryArgs {
+    /// The building, site or campus to search for free rooms in
+    ///
+    /// Either its id (e.g. `5602`) or its short name (e.g. `MI`).
+    #[param(example = "5602")]
+    parent: String,
+    /// The start of the window, in which the rooms have to be free
+    #[param(example = "2039-01-19T08:00:00+01:00")]
+    from: DateTime<Utc>,
+    /// The end of the window, in which the rooms have to be free
+    ///
+    /// The window may be at most 31 days long.
+    #[param(example = "2039-01-19T10:00:00+01:00")]
+    to: DateTime<Utc>,
+    /// Only include rooms, whose usage contains this text (case-insensitive, german or english)
+    #[param(example = "seminar")]
+    usage: Option<String>,
+    /// Maximum number of rooms to return.
+    ///
+    /// Clamped to `1`..`200`.
+    #[param(default = 50, minimum = 1, maximum = 200)]
+    limit: Option<i64>,
+}
+
+/// Find free rooms
+///
+/// Lists all rooms inside `parent`, which have a calendar without any entries overlapping `from`..`to`.
+/// Rooms, whose calendar was not yet scraped, are omitted, as we cannot know whether they are free.
+///
+/// The most relevant rooms are returned first, at most `limit` of them.
+#[utoipa::path(
+    tags=["calendar"],
+    params(FreeRoomsQueryArgs, localisation::LangQueryArgs),
+    responses(
+        (status = 200, description = "**Free rooms** in the requested window", body = Vec<CalendarLocationResponse>, content_type = "application/json"),
+        (status = 400, description = "**Bad Request.** The window is empty or too long", body = String, content_type = "text/plain", example = "The window may be at most 31 days long"),
+    )
+)]
+#[get("/api/calendar/free-rooms")]
+pub async fn free_rooms_handler(
+    web::Query(args): web::Query<FreeRoomsQueryArgs>,
+    web::Query(lang): web::Query<localisation::LangQueryArgs>,
+    data: web::Data<crate::AppData>,
+) -> HttpResponse {
+    if let Err(e) = validate_window(&args.from, &args.to) {
+        return e;
+    }
+    let parent = args.parent.trim();
+    let usage = args
+        .usage
+        .as_deref()
+        .map(str::trim)
+        .filter(|u| !u.is_empty());
+    let limit = args.limit.unwrap_or(50).clamp(1, 200);
+    let rooms = CalendarLocation::fetch_free(
+        &data.pool,
+        parent,
+        usage,
+        &args.from,
+        &args.to,
+        lang.should_use_english(),
+        limit,
+    )
+    .await;
+    let rooms = match rooms {
+        Ok(rooms) => rooms,
+        Err(e) => {
+            error!(error = ?e, ?args, "could not get the free rooms");
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get free rooms, please try again later");
+        }
+    };
+    let rooms = rooms
+        .into_iter()
+        .map(CalendarLocationResponse::from)
+        .collect::<Vec<_>>();
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::MaxAge(5 * 60), // valid for 5min
+            CacheDirective::Public,
+        ]))
+        .json(rooms)
+}
+
+#[cfg(test)]
+mod db_tests {
+    use actix_web::{test, App};
+    use chrono::{Duration, TimeZone};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::db::calendar::{Event, EventType};
+    use crate::setup::tests::PostgresTestContainer;
+    use crate::AppData;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 7, 1, hour, 0, 0).unwrap()
+    }
+
+    /// A campus or building without a calendar
+    async fn insert_parent(pool: &sqlx::PgPool, key: &str, r#type: &str, short_name: Option<&str>) {
+        let data = json!({"name": key, "short_name": short_name, "type": r#type, "type_common_name": r#type, "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"}, "parents": ["root"], "parent_names": ["Standorte"]});
+        for lang in ["de", "en"] {
+            let query = format!("INSERT INTO {lang}(key,data) VALUES ($1,$2)");
+            sqlx::query(&query)
+                .bind(key)
+                .bind(&data)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// A room with a calendar inside `parents`, with its german and english `usage`
+    async fn insert_room(
+        pool: &sqlx::PgPool,
+        key: &str,
+        parents: &[&str],
+        usage: (&str, &str),
+        rank_combined: i32,
+        last_calendar_scrape_at: Option<DateTime<Utc>>,
+    ) {
+        for (lang, usage) in [("de", usage.0), ("en", usage.1)] {
+            let data = json!({"id": key, "name": format!("{key} ({usage})"), "type": "room", "type_common_name": usage, "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"}, "parents": parents, "parent_names": parents, "usage": {"name": usage}, "props": {"calendar_url": format!("https://campus.tum.de/{key}")}, "ranking_factors": {"rank_combined": rank_combined}});
+            let query =
+                format!("INSERT INTO {lang}(key,data,last_calendar_scrape_at) VALUES ($1,$2,$3)");
+            sqlx::query(&query)
+                .bind(key)
+                .bind(&data)
+                .bind(last_calendar_scrape_at)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn load_sample_data(pool: &sqlx::PgPool) {
+        let scraped_at = Some(at(6));
+        let mi = ["root", "garching", "5602"];
+        let atlashalle = ["root", "garching", "5121"];
+        let seminar_room = ("Seminarraum", "Seminar room");
+        insert_parent(pool, "garching", "campus", None).await;
+        insert_parent(pool, "5602", "building", Some("MI")).await;
+        insert_parent(pool, "5121", "building", None).await;
+        insert_room(
+            pool,
+            "5602.EG.001",
+            &mi,
+            ("Hörsaal", "Lecture hall"),
+            100,
+            scraped_at,
+        )
+        .await;
+        insert_room(pool, "5602.EG.002", &mi, seminar_room, 50, scraped_at).await;
+        // not yet scraped => we cannot know whether it is free
+        insert_room(pool, "5602.EG.003", &mi, seminar_room, 50, None).await;
+        insert_room(
+            pool,
+            "5121.EG.003",
+            &atlashalle,
+            ("Serverraum", "Server room"),
+            10,
+            scraped_at,
+        )
+        .await;
+        insert_room(
+            pool,
+            "5121.EG.004",
+            &atlashalle,
+            seminar_room,
+            10,
+            scraped_at,
+        )
+        .await;
+        // on another campus
+        insert_room(
+            pool,
+            "0501.01.001",
+            &["root", "stammgelaende", "0501"],
+            seminar_room,
+            50,
+            scraped_at,
+        )
+        .await;
+
+        let mut tx = pool.begin().await.unwrap();
+        for (id, room_code) in [(1, "5602.EG.001"), (2, "5121.EG.004")] {
+            let event = Event {
+                id,
+                room_code: room_code.into(),
+                start_at: at(10),
+                end_at: at(12),
+                title_de: "Quantenteleportation".into(),
+                title_en: "Quantum teleportation".into(),
+                stp_type: None,
+                entry_type: EventType::Lecture.to_string(),
+                detailed_entry_type: "Abhaltung".into(),
+            };
+            event.store(&mut tx).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+        for view in ["parents", "ranking_factors"] {
+            sqlx::query(&format!("REFRESH MATERIALIZED VIEW {view}"))
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    fn free_rooms(query: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> test::TestRequest {
+        let from = from.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let to = to.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        test::TestRequest::get().uri(&format!(
+            "/api/calendar/free-rooms?{query}&from={from}&to={to}"
+        ))
+    }
+
+    async fn run_testcase(resp: actix_web::dev::ServiceResponse) -> (u16, Value) {
+        let status = resp.status().as_u16();
+        let body = test::read_body(resp).await;
+        // errors are returned as plain text
+        let body = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+        (status, body)
+    }
+
+    fn keys(rooms: &Value) -> Vec<&str> {
+        rooms
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|room| room["key"].as_str().unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_free_rooms_get() {
+        let pg = PostgresTestContainer::new().await;
+        load_sample_data(&pg.pool).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData::from(pg.pool.clone())))
+                .service(free_rooms_handler),
+        )
+        .await;
+        {
+            // empty window
+            let req = free_rooms("parent=5602", at(12), at(10)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 400);
+            assert_eq!(
+                actual,
+                json!("The end of the window has to be after its start")
+            );
+        }
+        {
+            // window too long
+            let req = free_rooms("parent=5602", at(10), at(10) + Duration::days(32)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 400);
+            assert_eq!(actual, json!("The window may be at most 31 days long"));
+        }
+        {
+            // a building, where the lecture hall is occupied
+            let req = free_rooms("parent=5602", at(10), at(12)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(
+                actual,
+                json!([{
+                    "key": "5602.EG.002",
+                    "name": "5602.EG.002 (Seminarraum)",
+                    "last_calendar_scrape_at": "2024-07-01T06:00:00Z",
+                    "calendar_url": "https://campus.tum.de/5602.EG.002",
+                    "type_common_name": "Seminarraum",
+                    "type": "room",
+                }])
+            );
+        }
+        {
+            // the same building, by its short name
+            let req = free_rooms("parent=mi", at(10), at(12)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(keys(&actual), vec!["5602.EG.002"]);
+        }
+        {
+            // after the lecture, the lecture hall is free again
+            let req = free_rooms("parent=5602", at(12), at(14)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(keys(&actual), vec!["5602.EG.001", "5602.EG.002"]);
+        }
+        {
+            // a campus, the most relevant rooms first
+            let req = free_rooms("parent=garching", at(10), at(12)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(keys(&actual), vec!["5602.EG.002", "5121.EG.003"]);
+            let req = free_rooms("parent=garching", at(12), at(14)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(
+                keys(&actual),
+                vec!["5602.EG.001", "5602.EG.002", "5121.EG.003", "5121.EG.004"]
+            );
+        }
+        {
+            // only the most relevant rooms
+            let req = free_rooms("parent=garching&limit=2", at(12), at(14)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(keys(&actual), vec!["5602.EG.001", "5602.EG.002"]);
+            // at least one room is returned
+            let req = free_rooms("parent=garching&limit=0", at(12), at(14)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(keys(&actual), vec!["5602.EG.001"]);
+        }
+        {
+            // only seminar rooms, by their german or english usage
+            for usage in ["seminar", "Seminar room"] {
+                let query = format!("parent=garching&usage={}", usage.replace(' ', "%20"));
+                let req = free_rooms(&query, at(12), at(14)).to_request();
+                let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+                assert_eq!(status, 200);
+                assert_eq!(keys(&actual), vec!["5602.EG.002", "5121.EG.004"]);
+            }
+        }
+        {
+            // in english
+            let req = free_rooms("parent=5602&lang=en", at(10), at(12)).to_request();
+            let (status, actual) = run_testcase(test::call_service(&app, req).await).await;
+            assert_eq!(status, 200);
+            assert_eq!(
+                actual,
+                json!([{
+                    "key": "5602.EG.002",
+                    "name": "5602.EG.002 (Seminar room)",
+                    "last_calendar_scrape_at": "2024-07-01T06:00:00Z",
+                    "calendar_url": "https://campus.tum.de/5602.EG.002",
+                    "type_common_name": "Seminar room",
+                    "type": "room",
+                }])
+            );
+        }
+    }
+}
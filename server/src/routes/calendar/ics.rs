@@ -0,0 +1,279 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use chrono_tz::Europe::Berlin;
+use serde::Deserialize;
+use tracing::error;
+
+use super::{fetch_calendar_location, EventTypeResponse};
+use crate::db::calendar::{CalendarLocation, Event};
+use crate::localisation;
+
+/// Lines longer than this (in octets, excluding the line break) have to be folded
+///
+/// See [RFC 5545, Section 3.1](https://www.rfc-editor.org/rfc/rfc5545#section-3.1)
+const MAX_LINE_OCTETS: usize = 75;
+/// How all datetimes are written, either in UTC (suffixed with `Z`) or in the local time of a `TZID`
+const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+/// Definition of the timezone of all events, so that clients don't have to know `Europe/Berlin`
+const VTIMEZONE_EUROPE_BERLIN: [&str; 17] = [
+    "BEGIN:VTIMEZONE",
+    "TZID:Europe/Berlin",
+    "BEGIN:DAYLIGHT",
+    "TZOFFSETFROM:+0100",
+    "TZOFFSETTO:+0200",
+    "TZNAME:CEST",
+    "DTSTART:19700329T020000",
+    "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU",
+    "END:DAYLIGHT",
+    "BEGIN:STANDARD",
+    "TZOFFSETFROM:+0200",
+    "TZOFFSETTO:+0100",
+    "TZNAME:CET",
+    "DTSTART:19701025T030000",
+    "RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU",
+    "END:STANDARD",
+    "END:VTIMEZONE",
+];
+
+#[derive(Deserialize, utoipa::IntoParams)]
+struct IcsPathParams {
+    /// ID of the room
+    #[param(example = "5602.EG.001")]
+    id: String,
+}
+
+/// Export a Calendar as iCalendar
+///
+/// Renders all known calendar entries of the room `id` as an [RFC 5545](https://www.rfc-editor.org/rfc/rfc5545) iCalendar.
+/// This url can be subscribed to in calendar clients like Outlook or Thunderbird.
+///
+/// The titles and categories of the entries are localised according to `lang`.
+#[utoipa::path(
+    tags=["calendar"],
+    params(IcsPathParams, localisation::LangQueryArgs),
+    responses(
+        (status = 200, description = "**Entries of the calendar** as an iCalendar", body = String, content_type = "text/calendar"),
+        (status = 404, description = "**Not found.** The requested location does not exist or does not have a calendar", body = String, content_type = "text/plain", example = "Not found"),
+        (status = 503, description = "**Not Ready.** please retry later", body = String, content_type = "text/plain", example = "Waiting for first sync with TUMonline"),
+    )
+)]
+#[get("/api/calendar/{id}.ics")]
+pub async fn ics_handler(
+    params: web::Path<IcsPathParams>,
+    web::Query(args): web::Query<localisation::LangQueryArgs>,
+    data: web::Data<crate::AppData>,
+) -> HttpResponse {
+    let location = match fetch_calendar_location(&data.pool, &params.id).await {
+        Ok(location) => location,
+        Err(e) => return e,
+    };
+    let events = match Event::fetch_all_of(&data.pool, &location.key).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!(error = ?e, key = location.key, "could not get entries from the db");
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get calendar entries, please try again later");
+        }
+    };
+    let calendar = render(&location, &events, args.should_use_english(), Utc::now());
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::MaxAge(60 * 60), // valid for 1h
+            CacheDirective::Public,
+        ]))
+        .content_type("text/calendar; charset=utf-8")
+        .body(calendar)
+}
+
+/// Renders the `events` of the `location` as an iCalendar
+///
+/// `now` is the time this iCalendar was created at (the `DTSTAMP`).
+fn render(
+    location: &CalendarLocation,
+    events: &[Event],
+    should_use_english: bool,
+    now: DateTime<Utc>,
+) -> String {
+    let mut ics = ICalendarWriter::default();
+    ics.line("BEGIN:VCALENDAR");
+    ics.line("VERSION:2.0");
+    ics.line("PRODID:-//NavigaTUM//Room calendar//EN");
+    ics.line("CALSCALE:GREGORIAN");
+    ics.line("METHOD:PUBLISH");
+    ics.line(&format!("X-WR-CALNAME:{}", escape(&location.name)));
+    ics.line("X-WR-TIMEZONE:Europe/Berlin");
+    for line in VTIMEZONE_EUROPE_BERLIN {
+        ics.line(line);
+    }
+    let dtstamp = now.format(DATETIME_FORMAT);
+    for event in events {
+        let title = if should_use_english && !event.title_en.is_empty() {
+            &event.title_en
+        } else {
+            &event.title_de
+        };
+        let entry_type = EventTypeResponse::from(event.entry_type.clone());
+        let start = event
+            .start_at
+            .with_timezone(&Berlin)
+            .format(DATETIME_FORMAT);
+        let end = event.end_at.with_timezone(&Berlin).format(DATETIME_FORMAT);
+        ics.line("BEGIN:VEVENT");
+        // ids are assigned by TUMonline and stay the same if an event is moved
+        ics.line(&format!("UID:{id}@nav.tum.de", id = event.id));
+        ics.line(&format!("DTSTAMP:{dtstamp}Z"));
+        ics.line(&format!("DTSTART;TZID=Europe/Berlin:{start}"));
+        ics.line(&format!("DTEND;TZID=Europe/Berlin:{end}"));
+        ics.line(&format!("SUMMARY:{}", escape(title)));
+        ics.line(&format!("LOCATION:{}", escape(&location.name)));
+        ics.line(&format!(
+            "CATEGORIES:{},{}",
+            escape(entry_type.localised_name(should_use_english)),
+            escape(&event.detailed_entry_type)
+        ));
+        if let Some(stp_type) = &event.stp_type {
+            ics.line(&format!("DESCRIPTION:{}", escape(stp_type)));
+        }
+        ics.line("END:VEVENT");
+    }
+    ics.line("END:VCALENDAR");
+    ics.0
+}
+
+/// Collects content lines, terminating and folding them as required by RFC 5545
+#[derive(Default)]
+struct ICalendarWriter(String);
+impl ICalendarWriter {
+    fn line(&mut self, line: &str) {
+        let mut line_octets = 0;
+        for c in line.chars() {
+            // multi-octet characters must not be split
+            if line_octets + c.len_utf8() > MAX_LINE_OCTETS {
+                self.0.push_str("\r\n ");
+                line_octets = 1;
+            }
+            self.0.push(c);
+            line_octets += c.len_utf8();
+        }
+        self.0.push_str("\r\n");
+    }
+}
+
+/// Escapes a `TEXT` value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn location() -> CalendarLocation {
+        CalendarLocation {
+            key: "5602.EG.001".into(),
+            name: "5602.EG.001 (MI HS 1, Friedrich L. Bauer Hörsaal)".into(),
+            last_calendar_scrape_at: None,
+            calendar_url: None,
+            type_common_name: "Hörsaal".into(),
+            r#type: "room".into(),
+        }
+    }
+
+    fn event() -> Event {
+        Event {
+            id: 42,
+            room_code: "5602.EG.001".into(),
+            start_at: Utc.with_ymd_and_hms(2024, 7, 1, 8, 15, 0).unwrap(),
+            end_at: Utc.with_ymd_and_hms(2024, 7, 1, 9, 45, 0).unwrap(),
+            title_de: "Einführung in die Informatik; Teil 1".into(),
+            title_en: "Introduction to Computer Science; Part 1".into(),
+            stp_type: Some("Vorlesung mit Zentralübung".into()),
+            entry_type: "lecture".into(),
+            detailed_entry_type: "Abhaltung".into(),
+        }
+    }
+
+    #[test]
+    fn events_are_rendered_in_local_time() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let mut winter_event = event();
+        winter_event.id = 43;
+        winter_event.start_at = Utc.with_ymd_and_hms(2024, 12, 2, 8, 15, 0).unwrap();
+        winter_event.end_at = Utc.with_ymd_and_hms(2024, 12, 2, 9, 45, 0).unwrap();
+        let ics = render(&location(), &[event(), winter_event], false, now);
+        let lines = ics.split("\r\n").collect::<Vec<&str>>();
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&""), "the last line is terminated too");
+        assert!(lines.contains(&"UID:42@nav.tum.de"));
+        assert!(lines.contains(&"DTSTAMP:20240601T120000Z"));
+        // CEST is UTC+2
+        assert!(lines.contains(&"DTSTART;TZID=Europe/Berlin:20240701T101500"));
+        assert!(lines.contains(&"DTEND;TZID=Europe/Berlin:20240701T114500"));
+        // CET is UTC+1
+        assert!(lines.contains(&"DTSTART;TZID=Europe/Berlin:20241202T091500"));
+        assert!(lines.contains(&"SUMMARY:Einführung in die Informatik\\; Teil 1"));
+        assert!(lines.contains(&"CATEGORIES:Vorlesung,Abhaltung"));
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VTIMEZONE").count(), 1);
+    }
+
+    #[test]
+    fn components_are_balanced() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let ics = render(&location(), &[event()], false, now);
+        let lines = ics.split("\r\n").collect::<Vec<&str>>();
+        for component in ["VCALENDAR", "VTIMEZONE", "DAYLIGHT", "STANDARD", "VEVENT"] {
+            let begin = format!("BEGIN:{component}");
+            let end = format!("END:{component}");
+            assert_eq!(lines.iter().filter(|l| **l == begin).count(), 1);
+            assert_eq!(lines.iter().filter(|l| **l == end).count(), 1);
+        }
+        // components have to be closed in the reverse order they were opened in
+        let mut open_components = Vec::new();
+        for line in lines {
+            if let Some(component) = line.strip_prefix("BEGIN:") {
+                open_components.push(component);
+            } else if let Some(component) = line.strip_prefix("END:") {
+                assert_eq!(open_components.pop(), Some(component), "unexpected {line}");
+            }
+        }
+        assert!(
+            open_components.is_empty(),
+            "{open_components:?} are not closed"
+        );
+    }
+
+    #[test]
+    fn titles_and_categories_are_localised() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let ics = render(&location(), &[event()], true, now);
+        assert!(ics.contains("\r\nSUMMARY:Introduction to Computer Science\\; Part 1\r\n"));
+        assert!(ics.contains("\r\nCATEGORIES:Lecture,Abhaltung\r\n"));
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let mut ics = ICalendarWriter::default();
+        let summary = format!("SUMMARY:{}", "ü".repeat(40));
+        ics.line(&summary);
+        for line in ics.0.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_OCTETS, "{line} is too long");
+        }
+        let unfolded = ics.0.replace("\r\n ", "");
+        assert_eq!(unfolded, format!("{summary}\r\n"));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape("a,b;c\\d\ne"), "a\\,b\\;c\\\\d\\ne");
+    }
+}
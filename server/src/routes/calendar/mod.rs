@@ -1,5 +1,5 @@
 use actix_web::{post, web, HttpResponse};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::error;
@@ -7,6 +7,14 @@ use tracing::error;
 use crate::db::calendar::{CalendarLocation, Event, LocationEvents};
 use actix_web::http::header::{CacheControl, CacheDirective};
 
+pub mod availability;
+pub mod changes;
+pub mod free_rooms;
+pub mod ics;
+
+/// Longest window which can be requested at once
+const MAX_WINDOW: Duration = Duration::days(31);
+
 #[expect(
     unused_imports,
     reason = "has to be imported as otherwise utoipa generates incorrect code"
@@ -128,6 +136,43 @@ impl From<LocationEvents> for LocationEventsResponse {
         }
     }
 }
+/// Checks, that `start`..`end` is neither empty nor longer than [`MAX_WINDOW`]
+fn validate_window(start: &DateTime<Utc>, end: &DateTime<Utc>) -> Result<(), HttpResponse> {
+    if end <= start {
+        return Err(HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("The end of the window has to be after its start"));
+    }
+    if *end - *start > MAX_WINDOW {
+        return Err(HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("The window may be at most 31 days long"));
+    }
+    Ok(())
+}
+/// The location `id`, if it exists and has a scraped calendar
+async fn fetch_calendar_location(
+    pool: &sqlx::PgPool,
+    id: &str,
+) -> Result<CalendarLocation, HttpResponse> {
+    let ids = [id.replace(|c: char| c.is_whitespace() || c.is_control(), "")];
+    let location = match CalendarLocation::get_locations(pool, &ids).await {
+        Ok(l) => l.0.into_iter().next(),
+        Err(e) => {
+            error!(error = ?e, ids = ?ids, "could not get the location");
+            return Err(HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not get calendar entries, please try again later"));
+        }
+    };
+    let Some(location) = location else {
+        return Err(HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body("Not found"));
+    };
+    validate_locations(&ids, std::slice::from_ref(&location))?;
+    Ok(location)
+}
 fn validate_locations(ids: &[String], locations: &[CalendarLocation]) -> Result<(), HttpResponse> {
     for id in ids {
         if !locations.iter().any(|l| &l.key == id) {
@@ -281,6 +326,23 @@ impl From<String> for EventTypeResponse {
         }
     }
 }
+impl EventTypeResponse {
+    /// Human-readable name of this type
+    fn localised_name(&self, should_use_english: bool) -> &'static str {
+        match (self, should_use_english) {
+            (EventTypeResponse::Lecture, false) => "Vorlesung",
+            (EventTypeResponse::Lecture, true) => "Lecture",
+            (EventTypeResponse::Exercise, false) => "Übung",
+            (EventTypeResponse::Exercise, true) => "Exercise",
+            (EventTypeResponse::Exam, false) => "Prüfung",
+            (EventTypeResponse::Exam, true) => "Exam",
+            (EventTypeResponse::Barred, false) => "Gesperrt",
+            (EventTypeResponse::Barred, true) => "Barred",
+            (EventTypeResponse::Other, false) => "Sonstiges",
+            (EventTypeResponse::Other, true) => "Other",
+        }
+    }
+}
 #[cfg(test)]
 mod db_tests {
     use actix_web::http::header::ContentType;
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod calendar;
 pub mod feedback;
 pub mod locations;
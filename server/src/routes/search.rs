@@ -1,17 +1,33 @@
 use std::fmt::{Debug, Formatter};
+use std::sync::LazyLock;
 use std::time::Instant;
 
-use crate::search_executor::{ResultFacet, ResultsSection};
+use crate::db::search_misses::SearchMiss;
+use crate::localisation;
+use crate::search_executor::{
+    BatchMatch, FacetDistribution, ParsedQuery, ResultFacet, ResultsSection, SearchExplanation,
+    Suggestion, UnavailableRooms, UnavailableRoomsKey,
+};
 use crate::AppData;
 use actix_web::http::header::{CacheControl, CacheDirective};
-use actix_web::{get, web, HttpResponse};
+use actix_web::{get, post, web, HttpResponse};
 use cached::proc_macro::cached;
+use cached::{Cached, Return};
+use futures::StreamExt;
 use meilisearch_sdk::client::Client;
+use prometheus::{IntCounterVec, Opts, Registry};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use tokio::join;
 use tracing::{debug, error};
 use unicode_truncate::UnicodeTruncateStr;
 
+#[expect(
+    unused_imports,
+    reason = "has to be imported as otherwise utoipa generates incorrect code"
+)]
+use serde_json::json;
+
 #[derive(Deserialize, Debug, Default, utoipa::IntoParams, utoipa::ToSchema)]
 pub struct SearchQueryArgs {
     /// string you want to search for.
@@ -22,7 +38,15 @@ pub struct SearchQueryArgs {
     /// - `in:<parent>`/`@<parent>`: Only return rooms in the given parent (e.g. `in:5304` or `in:garching`)
     /// - `usage:<type>`/`nutzung:<usage>`/`=<usage>`: Only return entries of the given usage (e.g. `usage:wc` or `usage:büro`)
     /// - `type:<type>`: Only return entries of the given type (e.g. `type:building` or `type:room`)
+    /// - `floor:<floor>`/`etage:<floor>`/`level:<floor>`: Only return rooms on the given floor (e.g. `floor:EG`, `floor:1`, `floor:OG1` or `floor:UG`)
+    /// - `operator:<code>`/`org:<code>`: Only return entries operated by the given organisation, using its code or id (e.g. `operator:TUS7000` or `org:51901`)
+    /// - `free:now`/`free:<HH:MM>-<HH:MM>`: Only return rooms with a calendar, which have no events in the next hour or in the given time window of today (e.g. `free:now` or `free:14:00-16:00`). Invalid windows (e.g. `free:25:00-26:00`) are rejected
     /// - `near:<lat>,<lon>`: prioritise sorting the entries by distance to a coordinate
+    /// - `within:<lat>,<lon>,<radius_m>`: Only return entries within the given radius (in meters) around a coordinate
+    /// - `bbox:<lat>,<lon>,<lat>,<lon>`: Only return entries inside the bounding box spanned by two opposite corners
+    ///
+    /// Each filter can be negated by prefixing it with `-`, `!` or `not:` (e.g. `-in:5606`, `not:usage:wc` or `!type:virtual_room`).
+    /// Negated filters exclude all entries matching them.
     #[schema(
         min_length = 1,
         examples(
@@ -48,6 +72,27 @@ pub struct SearchQueryArgs {
     /// Be aware that Nominatim (which we use to do this search) is really slow (~100ms).
     /// Only activate this when you really need it.
     search_addresses: Option<bool>,
+    /// Comma-separated list of facets, for which the distribution of values should be returned.
+    ///
+    /// Supported facets are `usage`, `type`, `campus` and `parent_building_names`.
+    /// Unknown facets are ignored.
+    /// The counts are computed over all entries matching the query, not just the returned ones.
+    /// If not present (the default), no distribution is returned.
+    #[schema(examples("usage,type", "campus", "usage,type,campus,parent_building_names"))]
+    facets: Option<String>,
+    /// Only return a single page of the given section.
+    ///
+    /// The page starts at `offset` and its size is controlled by `limit_rooms`/`limit_buildings` (clamped to `1`..`1000`).
+    /// In this mode, results are not merged across sections, so that pages stay consistent with `estimatedTotalHits`.
+    /// The returned section contains `next_offset` as a cursor if there is a next page.
+    /// `search_addresses` is ignored when paginating and facet distributions (see `facets`) are only counted for this section.
+    paginate: Option<PaginatedFacet>,
+    /// Number of entries of the section selected via `paginate` to skip.
+    ///
+    /// Clamped to `0`..`1000`.
+    /// Ignored if `paginate` is not set.
+    #[schema(default = 0, maximum = 1000, minimum = 0)]
+    offset: Option<usize>,
     /// Maximum number of buildings/sites to return.
     ///
     /// Clamped to `0`..`1000`.
@@ -88,12 +133,41 @@ pub struct SearchQueryArgs {
         examples("/u0017", "</em>", "</ais-highlight-00000000>")
     )]
     post_highlight: Option<String>,
+    /// Include how the query was interpreted and ranked in the response (see `debug` in the response).
+    ///
+    /// Intended for finding out why a query behaves unexpectedly.
+    /// As this executes the search a second time, please do not use this for regular searches.
+    #[schema(default = false)]
+    debug: Option<bool>,
 }
 
 /// Returned search results by this
 #[derive(Serialize, utoipa::ToSchema)]
 pub struct SearchResponse {
     sections: Vec<ResultsSection>,
+    /// Distribution of values for the facets requested via `facets`
+    ///
+    /// Only present if `facets` was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<FacetDistribution>,
+    /// A corrected query, which likely returns results
+    ///
+    /// Only present if the query did not return any results and a misspelled word was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "garching")]
+    did_you_mean: Option<String>,
+    /// Whether meilisearch was unavailable and the results are from a less sophisticated fallback search instead
+    ///
+    /// Degraded results only match the names of entries.
+    /// Filters, highlighting, `facets`, `paginate` and `search_addresses` are ignored.
+    #[schema(example = false)]
+    degraded: bool,
+    /// How the query was interpreted and ranked
+    ///
+    /// Only present if `debug` was requested and meilisearch is available.
+    /// The format of this is not stable and may change at any time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<SearchExplanation>,
     /// Time the search took in the server side, not including network delay
     ///
     /// Maximum as timeout.
@@ -107,8 +181,20 @@ impl Debug for SearchResponse {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut base = f.debug_struct("SearchResponse");
         base.field("time_ms", &self.time_ms);
+        if self.degraded {
+            base.field("degraded", &self.degraded);
+        }
+        if let Some(facets) = &self.facets {
+            base.field("facets", facets);
+        }
+        if let Some(did_you_mean) = &self.did_you_mean {
+            base.field("did_you_mean", did_you_mean);
+        }
         for section in self.sections.iter() {
             match section.facet {
+                ResultFacet::ExactMatch => {
+                    base.field("exact_match", section);
+                }
                 ResultFacet::SitesBuildings => {
                     base.field("sites_buildings", section);
                 }
@@ -153,23 +239,126 @@ impl Default for Limits {
 
 impl From<&SearchQueryArgs> for Limits {
     fn from(args: &SearchQueryArgs) -> Self {
-        let total_count = args.limit_all.unwrap_or(10).clamp(0, 1_000);
+        Self::clamped(
+            args.limit_buildings,
+            args.limit_rooms,
+            args.limit_all,
+            1_000,
+        )
+    }
+}
+
+impl From<&BatchSearchRequest> for Limits {
+    fn from(req: &BatchSearchRequest) -> Self {
+        // every query of a batch is searched with these limits
+        Self::clamped(
+            req.limit_buildings,
+            req.limit_rooms,
+            req.limit_all,
+            MAX_BATCH_LIMIT,
+        )
+    }
+}
+
+impl Limits {
+    fn clamped(
+        limit_buildings: Option<usize>,
+        limit_rooms: Option<usize>,
+        limit_all: Option<usize>,
+        max: usize,
+    ) -> Self {
+        let total_count = limit_all.unwrap_or(10).clamp(0, max);
         Self {
-            buildings_count: args
-                .limit_buildings
-                .unwrap_or(5)
-                .clamp(0, 1_000)
-                .min(total_count),
-            rooms_count: args
-                .limit_rooms
-                .unwrap_or(10)
-                .clamp(0, 1_000)
-                .min(total_count),
+            buildings_count: limit_buildings.unwrap_or(5).clamp(0, max).min(total_count),
+            rooms_count: limit_rooms.unwrap_or(10).clamp(0, max).min(total_count),
             total_count,
         }
     }
 }
 
+/// Sections which can be paginated
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaginatedFacet {
+    SitesBuildings,
+    Rooms,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// A single page of one section
+pub struct Page {
+    pub facet: PaginatedFacet,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Page {
+    fn from_args(args: &SearchQueryArgs) -> Option<Self> {
+        let facet = args.paginate?;
+        // in contrast to Limits, the page size is not constrained by limit_all
+        let limit = match facet {
+            PaginatedFacet::SitesBuildings => args.limit_buildings.unwrap_or(5),
+            PaginatedFacet::Rooms => args.limit_rooms.unwrap_or(10),
+        };
+        Some(Self {
+            facet,
+            offset: args.offset.unwrap_or(0).clamp(0, 1_000),
+            // an empty page could not point to the next one
+            limit: limit.clamp(1, 1_000),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+/// Facets for which the distribution of values is requested
+pub struct Facets {
+    pub usage: bool,
+    pub r#type: bool,
+    pub campus: bool,
+    pub parent_building_names: bool,
+}
+impl Debug for Facets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.as_meilisearch_facets())
+            .finish()
+    }
+}
+
+impl From<&SearchQueryArgs> for Facets {
+    fn from(args: &SearchQueryArgs) -> Self {
+        let mut facets = Self::default();
+        for facet in args.facets.as_deref().unwrap_or_default().split(',') {
+            match facet.trim() {
+                "usage" => facets.usage = true,
+                "type" => facets.r#type = true,
+                "campus" => facets.campus = true,
+                "parent_building_names" => facets.parent_building_names = true,
+                _ => {}
+            }
+        }
+        facets
+    }
+}
+
+impl Facets {
+    pub fn is_empty(&self) -> bool {
+        self.as_meilisearch_facets().is_empty()
+    }
+    /// The filterable attributes in meilisearch corresponding to the requested facets
+    pub fn as_meilisearch_facets(&self) -> Vec<&'static str> {
+        [
+            (self.usage, "usage"),
+            (self.r#type, "type"),
+            (self.campus, "campus"),
+            (self.parent_building_names, "parent_building_names"),
+        ]
+        .into_iter()
+        .filter_map(|(requested, facet)| requested.then_some(facet))
+        .collect()
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Highlighting {
     pub pre: String,
@@ -193,14 +382,22 @@ impl Default for Highlighting {
 }
 impl From<&SearchQueryArgs> for Highlighting {
     fn from(args: &SearchQueryArgs) -> Self {
-        let (pre, post) = (
-            args.pre_highlight
-                .clone()
-                .unwrap_or_else(|| "\u{0019}".to_string()),
-            args.post_highlight
-                .clone()
-                .unwrap_or_else(|| "\u{0017}".to_string()),
-        );
+        Self::truncated(
+            args.pre_highlight.as_deref(),
+            args.post_highlight.as_deref(),
+        )
+    }
+}
+
+impl From<&BatchSearchRequest> for Highlighting {
+    fn from(req: &BatchSearchRequest) -> Self {
+        Self::truncated(req.pre_highlight.as_deref(), req.post_highlight.as_deref())
+    }
+}
+
+impl Highlighting {
+    fn truncated(pre: Option<&str>, post: Option<&str>) -> Self {
+        let (pre, post) = (pre.unwrap_or("\u{0019}"), post.unwrap_or("\u{0017}"));
         // After 25 char this parameter kind of misses the point it tries to address.
         // for DOS Reasons this is truncated
         let (pre, post) = (
@@ -217,15 +414,18 @@ impl From<&SearchQueryArgs> for Highlighting {
 ///
 /// Instead of simply returning a list, the search results are returned in a way to provide a richer experience by splitting them up into sections. You might not necessarily need to implement all types of sections, or all sections features (if you just want to show a list). The order of sections is a suggested order to display them, but you may change this as you like.
 ///
+/// If the query is a room id in one of the commonly used formats (e.g. `5606.EG.036`, `3002@5510`, `MI 00.08.038` or `MW2001`), which resolves to exactly one entry, this entry is returned first in an `exact_match` section.
+/// It is then removed from the other sections.
+///
 /// Some fields support highlighting the query terms and it uses \x19 and \x17 to mark the beginning/end of a highlighted sequence.
 /// (See [Wikipedia](https://en.wikipedia.org/wiki/C0_and_C1_control_codes#Modified_C0_control_code_sets)).
 /// Some text-renderers will ignore them, but in case you do not want to use them, you might want to remove them from the responses via empty `pre_highlight` and `post_highlight` query parameters.
 #[utoipa::path(
     tags=["locations"],
-    params(SearchQueryArgs),
+    params(SearchQueryArgs, localisation::LangQueryArgs),
     responses(
         (status = 200, description = "Search entries", body = SearchResponse, content_type = "application/json"),
-        (status = 400, description= "**Bad Request.** Not all fields in the body are present as defined above", body = String, content_type = "text/plain", example = "Query deserialize error: invalid digit found in string"),
+        (status = 400, description= "**Bad Request.** Not all fields in the body are present as defined above, or a `free:` filter is not a valid window", body = String, content_type = "text/plain", example = "Query deserialize error: invalid digit found in string"),
         (status = 404, description = "**Not found.** `q` is empty. Since searching for nothing is nonsensical, we dont support this.", body = String, content_type = "text/plain", example = "Not found"),
         (status = 414, description = "**URI Too Long.** The uri you are trying to request is unreasonably long. Search querys dont have thousands of chars..", body = String, content_type = "text/plain"),
     )
@@ -234,19 +434,123 @@ impl From<&SearchQueryArgs> for Highlighting {
 pub async fn search_handler(
     data: web::Data<AppData>,
     web::Query(args): web::Query<SearchQueryArgs>,
+    // not flattened into SearchQueryArgs, as serde_urlencoded cannot deserialize numbers in flattened structs
+    web::Query(lang): web::Query<localisation::LangQueryArgs>,
 ) -> HttpResponse {
     let start_time = Instant::now();
-    let _ = data.meilisearch_initialised.read().await; // otherwise we could return empty results during initialisation
+    // during initialisation, meilisearch could return incomplete results => we use the database instead
+    let meilisearch_initialised = data.meilisearch_initialised.try_read().is_ok();
 
     let limits = Limits::from(&args);
     let highlighting = Highlighting::from(&args);
+    let facets = Facets::from(&args);
+    let page = Page::from_args(&args);
+    let explain = args.debug.unwrap_or(false);
     let q = args.q;
-    let search_addresses = args.search_addresses.unwrap_or(false);
-    debug!(q, ?limits, ?highlighting, "quested search");
-    let results_sections = cached_geoentry_search(q, highlighting, limits, search_addresses).await;
-    debug!(?results_sections, "searching returned");
+    let search_addresses = args.search_addresses.unwrap_or(false) && page.is_none();
+    debug!(q, ?limits, ?highlighting, ?facets, ?page, %lang, explain, "quested search");
+    let parsed_filters = ParsedQuery::from(q.as_str()).filters;
+    if let Some(window) = parsed_filters.malformed_free_windows().iter().next() {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(format!(
+                "{window} is not a valid window. Use free:now or free:HH:MM-HH:MM with a start before the end"
+            ));
+    }
+    let is_time_dependent = !parsed_filters.free_windows().is_empty();
+    // if the occupied rooms cannot be looked up, the free: filter cannot be applied => we use the database instead
+    let unavailable_rooms = if meilisearch_initialised && is_time_dependent {
+        crate::search_executor::unavailable_rooms(&data.pool, &q).await
+    } else {
+        Some(UnavailableRooms::default())
+    };
+    let explanation = async {
+        if !explain || !meilisearch_initialised {
+            return None;
+        }
+        let unavailable_rooms = unavailable_rooms.as_ref()?;
+        let ms_url =
+            std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
+        let Ok(client) = Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok()) else {
+            error!("Failed to create a meilisearch client");
+            return None;
+        };
+        crate::search_executor::explain(
+            &client,
+            &q,
+            highlighting.clone(),
+            limits,
+            unavailable_rooms.keys(),
+        )
+        .await
+    };
+    let geoentry_search = async {
+        if !meilisearch_initialised {
+            debug!("meilisearch is not initialised yet");
+            return None;
+        }
+        let unavailable_rooms = unavailable_rooms.clone()?;
+        let results = cached_geoentry_search(
+            q.clone(),
+            highlighting.clone(),
+            limits,
+            facets,
+            page,
+            search_addresses,
+            lang,
+            unavailable_rooms,
+        );
+        match results.await {
+            Ok(results) => {
+                let result = if results.was_cached { "hit" } else { "miss" };
+                SEARCH_CACHE_LOOKUPS.with_label_values(&[result]).inc();
+                Some(results.value)
+            }
+            Err(e) => {
+                error!(error = ?e, "Error searching for results");
+                None
+            }
+        }
+    };
+    let ((geoentry_search, exact_match), explanation) = join!(
+        async {
+            match page {
+                Some(_) => (geoentry_search.await, None),
+                None => join!(
+                    geoentry_search,
+                    crate::search_executor::exact_match(&data.pool, &q, lang)
+                ),
+            }
+        },
+        explanation
+    );
+    let degraded = geoentry_search.is_none();
+    let (mut results_sections, facet_distribution) = match geoentry_search {
+        Some(results) => results,
+        None => {
+            let fallback =
+                crate::search_executor::do_fallback_search(&data.pool, &q, limits, lang).await;
+            (fallback.0, None)
+        }
+    };
+    if let Some(exact_match) = exact_match {
+        for section in results_sections.iter_mut() {
+            section.remove_entries_of(&exact_match);
+        }
+        results_sections.insert(0, exact_match);
+    }
+    debug!(?results_sections, ?facet_distribution, "searching returned");
 
-    if results_sections.len() > 3 {
+    let is_first_page = page.is_none_or(|p| p.offset == 0);
+    let is_zero_hit = is_first_page && results_sections.iter().all(ResultsSection::is_empty);
+    let did_you_mean = if is_zero_hit && !degraded {
+        record_miss(&data.pool, SearchMiss::zero_hits(&q));
+        crate::search_executor::did_you_mean(&data.pool, &q).await
+    } else {
+        None
+    };
+
+    if results_sections.len() > 4 {
         error!(
             returned_section_cnt = results_sections.len(),
             "searching did not return expected the amount of sections it expected",
@@ -257,45 +561,423 @@ pub async fn search_handler(
     }
     let search_results = SearchResponse {
         sections: results_sections,
+        facets: facet_distribution,
+        did_you_mean,
+        degraded,
+        debug: explanation,
         time_ms: start_time.elapsed().as_millis() as u32,
     };
+    let max_age = if degraded {
+        60 // meilisearch should be available again soon
+    } else if is_time_dependent {
+        60 // rooms only stay free for so long
+    } else {
+        2 * 24 * 60 * 60 // valid for 2d
+    };
     HttpResponse::Ok()
         .insert_header(CacheControl(vec![
-            CacheDirective::MaxAge(2 * 24 * 60 * 60), // valid for 2d
+            CacheDirective::MaxAge(max_age),
             CacheDirective::Public,
         ]))
         .json(search_results)
 }
 
+/// Records the `miss` for later analysis, without delaying the response
+fn record_miss(pool: &PgPool, miss: SearchMiss) {
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        if let Err(e) = miss.store(&pool).await {
+            error!(error = ?e, ?miss, "could not record the search miss");
+        }
+    });
+}
+
+/// How often [`cached_geoentry_search`] was answered from the cache (`result="hit"`) or not (`result="miss"`)
+static SEARCH_CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let opts = Opts::new(
+        "search_cache_lookups_total",
+        "Lookups of search results in the in-process cache",
+    )
+    .namespace("navigatum_api");
+    IntCounterVec::new(opts, &["result"]).expect("specified metrics are valid")
+});
+
+/// Registers the metrics of the search, so that they are exposed on `/api/metrics`
+pub fn register_metrics(registry: &Registry) -> prometheus::Result<()> {
+    registry.register(Box::new(SEARCH_CACHE_LOOKUPS.clone()))
+}
+
+/// Drops all cached search results
+///
+/// Has to be called whenever the search index changes, as the results would be stale otherwise.
+pub async fn clear_search_cache() {
+    CACHED_GEOENTRY_SEARCH.lock().await.cache_clear();
+}
+
 // size=1 ~= 0.1Mi
-#[cached(size = 200)]
+// entries expire after 1h, as some results (e.g. the calendar-based `free:` filter) go stale even without a reindex
+// the unavailable rooms are part of the key via [`UnavailableRooms::cache_key`], so that time-dependent results are not served from the cache once stale
+#[expect(
+    clippy::too_many_arguments,
+    reason = "all of these are part of the cache key"
+)]
+#[cached(
+    size = 200,
+    time = 3600,
+    result = true,
+    with_cached_flag = true,
+    key = "(String, Highlighting, Limits, Facets, Option<Page>, bool, localisation::LangQueryArgs, UnavailableRoomsKey)",
+    convert = r#"{ (q.clone(), highlighting.clone(), limits, facets, page, search_addresses, lang, unavailable_rooms.cache_key()) }"#
+)]
 async fn cached_geoentry_search(
     q: String,
     highlighting: Highlighting,
     limits: Limits,
+    facets: Facets,
+    page: Option<Page>,
     search_addresses: bool,
-) -> Vec<ResultsSection> {
+    lang: localisation::LangQueryArgs,
+    unavailable_rooms: UnavailableRooms,
+) -> Result<Return<(Vec<ResultsSection>, Option<FacetDistribution>)>, meilisearch_sdk::errors::Error>
+{
+    let ms_url = std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
+    let client = Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok())?;
+    let geoentry_search = crate::search_executor::do_geoentry_search(
+        &client,
+        &q,
+        highlighting.clone(),
+        limits,
+        facets,
+        page,
+        lang,
+        unavailable_rooms.keys(),
+    );
+    if search_addresses {
+        let address_search = crate::search_executor::address_search(&q, highlighting);
+        let (address_search, geoentry_search) = join!(address_search, geoentry_search);
+        let mut geoentry_search = geoentry_search?;
+        geoentry_search.sections.0.extend(address_search.0);
+        Ok(Return::new((
+            geoentry_search.sections.0,
+            geoentry_search.facets,
+        )))
+    } else {
+        let geoentry_search = geoentry_search.await?;
+        Ok(Return::new((
+            geoentry_search.sections.0,
+            geoentry_search.facets,
+        )))
+    }
+}
+
+/// Maximum number of queries in a single batch search
+const MAX_BATCH_SIZE: usize = 100;
+/// Maximum length (in chars) of a single query in a batch search
+const MAX_BATCH_QUERY_LENGTH: usize = 200;
+/// Maximum of the `limit*` parameters in a batch search, as they apply to every query
+const MAX_BATCH_LIMIT: usize = 10;
+/// Maximum number of exact matches of a batch search, which are looked up in the database at once
+const MAX_BATCH_DB_CONCURRENCY: usize = 8;
+
+#[derive(Deserialize, Debug, Default, utoipa::ToSchema)]
+pub struct BatchSearchRequest {
+    /// The queries to resolve to entries.
+    ///
+    /// Query-filters (see `/api/search`) except `free:` are supported.
+    #[schema(
+        min_items = 1,
+        max_items = 100,
+        example = json!(["MW 1801", "Interims I HS 2", "5606.EG.036"])
+    )]
+    queries: Vec<String>,
+    /// Maximum number of buildings/sites to consider per query.
+    ///
+    /// Clamped to `0`..`10`.
+    #[schema(default = 5, maximum = 10, minimum = 0)]
+    limit_buildings: Option<usize>,
+    /// Maximum number of rooms to consider per query.
+    ///
+    /// Clamped to `0`..`10`.
+    #[schema(default = 10, maximum = 10, minimum = 0)]
+    limit_rooms: Option<usize>,
+    /// Maximum number of results to consider per query.
+    ///
+    /// Clamped to `1`..`10`.
+    #[schema(default = 10, maximum = 10, minimum = 1)]
+    limit_all: Option<usize>,
+    /// string to include in front of highlighted sequences.
+    ///
+    /// If this and `post_highlight` are empty, highlighting is disabled.
+    #[schema(default = "/u0019", max_length = 25, examples("/u0019", "<em>"))]
+    pre_highlight: Option<String>,
+    /// string to include after the highlighted sequences.
+    ///
+    /// If this and `pre_highlight` are empty, highlighting is disabled.
+    #[schema(default = "/u0017", max_length = 25, examples("/u0017", "</em>"))]
+    post_highlight: Option<String>,
+}
+
+/// Returned best matches by this
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct BatchSearchResponse {
+    /// The best match for each query, in the order of `queries`
+    results: Vec<BatchMatch>,
+    /// Time the search took in the server side, not including network delay
+    #[schema(example = 42)]
+    time_ms: u32,
+}
+
+/// Resolve many queries at once
+///
+/// This endpoint is designed for resolving free-text room names (e.g. from timetables) to entries in bulk.
+///
+/// In contrast to the `/api/search` endpoint, only the best match and how confident we are in it is returned for each query.
+/// All queries share a single round trip to the search backend, so this is considerably faster than searching them one by one.
+/// Room ids, which resolve to exactly one entry (see `/api/search`), are always the best match.
+///
+/// The `free:` filter is not supported, as it depends on the time of the request.
+/// Queries using it are rejected.
+#[utoipa::path(
+    tags=["locations"],
+    params(localisation::LangQueryArgs),
+    request_body = BatchSearchRequest,
+    responses(
+        (status = 200, description = "The best match per query", body = BatchSearchResponse, content_type = "application/json"),
+        (status = 400, description= "**Bad Request.** There are no queries, too many queries, a query is unreasonably long or uses the `free:` filter", body = String, content_type = "text/plain", example = "at most 100 queries are allowed per batch"),
+        (status = 429, description = "**Too many requests.** We are rate-limiting everyone's requests, please try again later."),
+        (status = 503, description = "**Service unavailable.** We cannot connect to the search backend", body = String, content_type = "text/plain", example = "Cannot perform search, please try again later"),
+    )
+)]
+#[post("")]
+pub async fn batch_search_handler(
+    data: web::Data<AppData>,
+    web::Json(req): web::Json<BatchSearchRequest>,
+    web::Query(lang): web::Query<localisation::LangQueryArgs>,
+) -> HttpResponse {
+    let start_time = Instant::now();
+    if req.queries.is_empty() {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("at least one query is required");
+    }
+    if req.queries.len() > MAX_BATCH_SIZE {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(format!(
+                "at most {MAX_BATCH_SIZE} queries are allowed per batch"
+            ));
+    }
+    if req
+        .queries
+        .iter()
+        .any(|q| q.chars().count() > MAX_BATCH_QUERY_LENGTH)
+    {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("query too long");
+    }
+    if let Some(q) = req.queries.iter().find(|q| {
+        let filters = ParsedQuery::from(q.as_str()).filters;
+        !filters.free_windows().is_empty() || !filters.malformed_free_windows().is_empty()
+    }) {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(format!(
+                "{q} uses the free: filter, which is not supported in batch searches"
+            ));
+    }
+    // there is no fallback, as searching hundreds of queries in the database would be too slow
+    if data.meilisearch_initialised.try_read().is_err() {
+        debug!("meilisearch is not initialised yet");
+        return HttpResponse::ServiceUnavailable()
+            .content_type("text/plain")
+            .body("Cannot perform search, please try again later");
+    }
+
+    let limits = Limits::from(&req);
+    let highlighting = Highlighting::from(&req);
+    debug!(queries = req.queries.len(), ?limits, ?highlighting, %lang, "quested batch search");
     let ms_url = std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
     let Ok(client) = Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok()) else {
         error!("Failed to create a meilisearch client");
-        return if search_addresses {
-            crate::search_executor::address_search(&q).await.0
-        } else {
-            vec![]
-        };
+        return HttpResponse::ServiceUnavailable()
+            .content_type("text/plain")
+            .body("Cannot perform search, please try again later");
     };
-    let geoentry_search =
-        crate::search_executor::do_geoentry_search(&client, &q, highlighting, limits);
-    if search_addresses {
-        let address_search = crate::search_executor::address_search(&q);
-        let (address_search, mut geoentry_search) = join!(address_search, geoentry_search);
-        geoentry_search.0.extend(address_search.0);
-        geoentry_search.0
-    } else {
-        geoentry_search.await.0
+    // buffered instead of buffer_unordered, as the exact matches are zipped with the matches by position
+    let exact_matches = futures::stream::iter(&req.queries)
+        .map(|q| crate::search_executor::exact_match(&data.pool, q, lang))
+        .buffered(MAX_BATCH_DB_CONCURRENCY)
+        .collect::<Vec<_>>();
+    let (matches, exact_matches) = join!(
+        crate::search_executor::do_batch_search(&client, &req.queries, highlighting, limits, lang),
+        exact_matches
+    );
+    let matches = match matches {
+        Ok(matches) => matches,
+        Err(e) => {
+            error!(error = ?e, "Error searching for results");
+            return HttpResponse::ServiceUnavailable()
+                .content_type("text/plain")
+                .body("Cannot perform search, please try again later");
+        }
+    };
+    let results = matches
+        .into_iter()
+        .zip(exact_matches)
+        .map(|(m, exact_match)| m.with_exact_match(exact_match))
+        .collect();
+    HttpResponse::Ok().json(BatchSearchResponse {
+        results,
+        time_ms: start_time.elapsed().as_millis() as u32,
+    })
+}
+
+/// Picks at this (zero-based) position or further down the list are considered as badly ranked
+const LOW_POSITION_THRESHOLD: usize = 5;
+
+#[derive(Deserialize, Debug, utoipa::ToSchema)]
+pub struct PickedResultRequest {
+    /// The query, which was searched for
+    #[schema(example = "mi hs 4", max_length = 200)]
+    q: String,
+    /// The id of the result the user picked
+    #[schema(example = "5602.EG.001", max_length = 50)]
+    id: String,
+    /// The zero-based position of the picked result.
+    ///
+    /// Counted over all sections in the order they were displayed.
+    #[schema(example = 7)]
+    position: usize,
+}
+
+/// Report a picked search result
+///
+/// Allows us to find out for which queries the wanted result is ranked too low.
+/// Only results picked far down the list are recorded, together with the query.
+/// Nothing identifying the user is stored.
+#[utoipa::path(
+    tags=["locations"],
+    request_body = PickedResultRequest,
+    responses(
+        (status = 204, description = "**Received** the pick"),
+        (status = 400, description= "**Bad Request.** The query or id is unreasonably long", body = String, content_type = "text/plain", example = "query or id too long"),
+        (status = 500, description = "**Internal Server Error.** We could not record the pick", body = String, content_type = "text/plain", example = "could not record the pick, please try again later"),
+        (status = 429, description = "**Too many requests.** We are rate-limiting everyone's requests, please try again later."),
+    )
+)]
+#[post("")]
+pub async fn picked_handler(
+    data: web::Data<AppData>,
+    web::Json(req): web::Json<PickedResultRequest>,
+) -> HttpResponse {
+    if req.q.chars().count() > 200 || req.id.chars().count() > 50 {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("query or id too long");
+    }
+    if req.position < LOW_POSITION_THRESHOLD {
+        return HttpResponse::NoContent().finish();
+    }
+    let miss = SearchMiss::low_position(&req.q, &req.id, req.position);
+    match miss.store(&data.pool).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!(error = ?e, ?miss, "could not record the search miss");
+            HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body("could not record the pick, please try again later")
+        }
     }
 }
 
+#[derive(Deserialize, Debug, Default, utoipa::IntoParams, utoipa::ToSchema)]
+pub struct SuggestQueryArgs {
+    /// The query the user is currently typing.
+    ///
+    /// Only the last (partial) token is completed, everything before it is kept as-is.
+    /// Query-filters like `in:` or `usage:` are completed to their valid values.
+    #[schema(min_length = 1, examples("560", "wc in:garch", "hörs", "ty"))]
+    q: String,
+    /// Maximum number of suggestions to return.
+    ///
+    /// Clamped to `1`..`20`
+    #[schema(default = 5, minimum = 1, maximum = 20, example = 5)]
+    limit: Option<usize>,
+}
+
+/// Returned suggestions by this
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct SuggestResponse {
+    /// Suggested completions, in the order they should be displayed
+    suggestions: Vec<Suggestion>,
+    /// Whether meilisearch is still being initialised and no suggestions could be computed
+    #[schema(example = false)]
+    degraded: bool,
+    /// Time the search took in the server side, not including network delay
+    #[schema(example = 3)]
+    time_ms: u32,
+}
+
+/// Suggest completions
+///
+/// This endpoint is designed for type-ahead completions in a search box.
+///
+/// In contrast to the `/api/search` endpoint, only the last token of the query is completed.
+/// This token may be a query-filter (e.g. `us` -> `usage:`), the value of a query-filter (e.g. `in:garch` -> `in:garching`) or a location (e.g. `560` -> `5606.EG.036`).
+/// Each suggestion contains the full completed query, which can directly be used for `/api/search`.
+#[utoipa::path(
+    tags=["locations"],
+    params(SuggestQueryArgs),
+    responses(
+        (status = 200, description = "Suggested completions", body = SuggestResponse, content_type = "application/json"),
+        (status = 400, description= "**Bad Request.** Not all fields in the body are present as defined above", body = String, content_type = "text/plain", example = "Query deserialize error: invalid digit found in string"),
+        (status = 414, description = "**URI Too Long.** The uri you are trying to request is unreasonably long. Search querys dont have thousands of chars..", body = String, content_type = "text/plain"),
+        (status = 503, description = "**Service unavailable.** We cannot connect to the search backend", body = String, content_type = "text/plain", example = "Cannot suggest completions, please try again later"),
+    )
+)]
+#[get("/api/search/suggest")]
+pub async fn suggest_handler(
+    data: web::Data<AppData>,
+    web::Query(args): web::Query<SuggestQueryArgs>,
+) -> HttpResponse {
+    let start_time = Instant::now();
+    // during initialisation, meilisearch could return incomplete results => we don't suggest anything
+    let meilisearch_initialised = data.meilisearch_initialised.try_read().is_ok();
+    let headers = CacheControl(vec![
+        CacheDirective::MaxAge(60), // facet values change with every reindex
+        CacheDirective::Public,
+    ]);
+    if !meilisearch_initialised {
+        debug!("meilisearch is not initialised yet");
+        return HttpResponse::Ok()
+            .insert_header(headers)
+            .json(SuggestResponse {
+                suggestions: Vec::new(),
+                degraded: true,
+                time_ms: start_time.elapsed().as_millis() as u32,
+            });
+    }
+
+    let limit = args.limit.unwrap_or(5).clamp(1, 20);
+    let ms_url = std::env::var("MIELI_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
+    let Ok(client) = Client::new(ms_url, std::env::var("MEILI_MASTER_KEY").ok()) else {
+        error!("Failed to create a meilisearch client");
+        return HttpResponse::ServiceUnavailable()
+            .content_type("text/plain")
+            .body("Cannot suggest completions, please try again later");
+    };
+    let suggestions = crate::search_executor::do_suggest(&client, &args.q, limit).await;
+    debug!(q = args.q, ?suggestions, "suggesting returned");
+    HttpResponse::Ok()
+        .insert_header(headers)
+        .json(SuggestResponse {
+            suggestions,
+            degraded: false,
+            time_ms: start_time.elapsed().as_millis() as u32,
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
@@ -367,6 +1049,36 @@ mod tests {
         assert_eq!(Limits::from(&input), expected);
     }
 
+    #[test]
+    fn test_limits_batch() {
+        let input = BatchSearchRequest {
+            limit_all: Some(3),
+            limit_rooms: Some(usize::MAX),
+            ..Default::default()
+        };
+        let expected = Limits {
+            total_count: 3,
+            rooms_count: 3,
+            buildings_count: 3,
+        };
+        assert_eq!(Limits::from(&input), expected);
+    }
+
+    #[test]
+    fn test_limits_batch_capped() {
+        let input = BatchSearchRequest {
+            limit_all: Some(1_000),
+            limit_buildings: Some(1_000),
+            ..Default::default()
+        };
+        let expected = Limits {
+            total_count: MAX_BATCH_LIMIT,
+            rooms_count: MAX_BATCH_LIMIT,
+            buildings_count: MAX_BATCH_LIMIT,
+        };
+        assert_eq!(Limits::from(&input), expected);
+    }
+
     #[test]
     fn test_highlighting_default() {
         let input = SearchQueryArgs::default();
@@ -426,4 +1138,55 @@ mod tests {
             assert_eq!(res.pre.len(), expected_length);
         }
     }
+
+    #[test]
+    fn test_facets_default() {
+        let facets = Facets::from(&SearchQueryArgs::default());
+        assert_eq!(facets, Facets::default());
+        assert!(facets.is_empty());
+    }
+    #[test]
+    fn test_facets() {
+        let input = SearchQueryArgs {
+            facets: Some("campus, usage,unknown,,type".to_string()),
+            ..Default::default()
+        };
+        let facets = Facets::from(&input);
+        assert_eq!(
+            facets.as_meilisearch_facets(),
+            vec!["usage", "type", "campus"]
+        );
+    }
+    #[test]
+    fn test_page() {
+        let input = SearchQueryArgs {
+            offset: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(Page::from_args(&input), None);
+        let input = SearchQueryArgs {
+            paginate: Some(PaginatedFacet::Rooms),
+            offset: Some(usize::MAX),
+            limit_all: Some(5),
+            limit_rooms: Some(50),
+            ..Default::default()
+        };
+        let expected = Page {
+            facet: PaginatedFacet::Rooms,
+            offset: 1000,
+            limit: 50,
+        };
+        assert_eq!(Page::from_args(&input), Some(expected));
+        let input = SearchQueryArgs {
+            paginate: Some(PaginatedFacet::Rooms),
+            limit_rooms: Some(0),
+            ..Default::default()
+        };
+        let expected = Page {
+            facet: PaginatedFacet::Rooms,
+            offset: 0,
+            limit: 1,
+        };
+        assert_eq!(Page::from_args(&input), Some(expected));
+    }
 }
@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Duration, DurationRound, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Europe::Berlin;
+use sqlx::PgPool;
+
+use super::parser::ParsedQuery;
+use crate::db::calendar::CalendarLocation;
+
+/// How long rooms have to be free for `free:now`
+const FREE_NOW: Duration = Duration::minutes(60);
+
+/// A window of today, in which rooms have to be free
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FreeWindow {
+    /// The next [`FREE_NOW`]
+    Now,
+    Today {
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+}
+
+impl FreeWindow {
+    /// Parses the `now` or `HH:MM-HH:MM` windows the lexer produces
+    fn parse(window: &str) -> Option<Self> {
+        if window == "now" {
+            return Some(Self::Now);
+        }
+        let (start, end) = window.split_once('-')?;
+        Some(Self::Today {
+            start: NaiveTime::parse_from_str(start, "%H:%M").ok()?,
+            end: NaiveTime::parse_from_str(end, "%H:%M").ok()?,
+        })
+    }
+    /// The window as `start`..`end`, relative to `now`
+    ///
+    /// `now` is truncated to the minute, so that the window (and thus the cache key of the search) stays the same for a minute.
+    fn resolve(self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        match self {
+            Self::Now => {
+                let start = now.duration_trunc(Duration::minutes(1)).unwrap_or(now);
+                (start, start + FREE_NOW)
+            }
+            Self::Today { start, end } => {
+                let today = now.with_timezone(&Berlin).date_naive();
+                (
+                    berlin_to_utc(today.and_time(start)),
+                    berlin_to_utc(today.and_time(end)),
+                )
+            }
+        }
+    }
+}
+
+/// Converts a local time in Berlin to UTC
+///
+/// Times skipped by the switch to summer time are moved past the switch.
+fn berlin_to_utc(local: NaiveDateTime) -> DateTime<Utc> {
+    Berlin
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            Berlin
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .map_or_else(|| local.and_utc(), |local| local.with_timezone(&Utc))
+}
+
+/// Identifies [`UnavailableRooms`] by their windows and the last calendar scrape
+pub type UnavailableRoomsKey = (Vec<(DateTime<Utc>, DateTime<Utc>)>, Option<DateTime<Utc>>);
+
+/// Rooms, which are not free during the `free:` windows of a query
+#[derive(Debug, Clone, Default)]
+pub struct UnavailableRooms {
+    /// The windows of the query as `start`..`end`
+    windows: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    /// When the calendar of any room was last scraped, before the rooms were looked up
+    last_scrape_at: Option<DateTime<Utc>>,
+    /// Keys of the rooms, sorted
+    keys: Vec<String>,
+}
+
+impl UnavailableRooms {
+    /// Keys of the rooms, sorted
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Key to cache results, which exclude these rooms, under
+    ///
+    /// For the same query, the rooms only depend on the windows and on the calendars at `last_scrape_at`.
+    /// Using just these keeps the cache key of the search small, even if thousands of rooms are occupied.
+    pub fn cache_key(&self) -> UnavailableRoomsKey {
+        (self.windows.clone(), self.last_scrape_at)
+    }
+}
+
+/// Rooms, which are not free during all `free:` windows of the query
+///
+/// Only rooms matching the `in:` and `usage:` filters of the query are looked up, as the others are not part of the results anyway.
+pub(super) async fn unavailable_rooms(
+    pool: &PgPool,
+    parsed_input: &ParsedQuery,
+    now: DateTime<Utc>,
+) -> sqlx::Result<UnavailableRooms> {
+    let mut windows = parsed_input
+        .filters
+        .free_windows()
+        .iter()
+        .filter_map(|window| FreeWindow::parse(window))
+        .map(|window| window.resolve(now))
+        .collect::<Vec<_>>();
+    windows.sort_unstable();
+    let parents = parsed_input
+        .filters
+        .parents()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let usages = parsed_input
+        .filters
+        .usages()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let last_scrape_at = CalendarLocation::fetch_last_scrape_at(pool).await?;
+    let mut keys = BTreeSet::new();
+    for (start, end) in &windows {
+        let unavailable =
+            CalendarLocation::fetch_unavailable_keys(pool, start, end, &parents, &usages).await?;
+        keys.extend(unavailable);
+    }
+    Ok(UnavailableRooms {
+        windows,
+        last_scrape_at,
+        keys: keys.into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn windows() {
+        assert_eq!(FreeWindow::parse("now"), Some(FreeWindow::Now));
+        assert_eq!(
+            FreeWindow::parse("09:00-16:30"),
+            Some(FreeWindow::Today {
+                start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(16, 30, 0).unwrap(),
+            })
+        );
+        assert_eq!(FreeWindow::parse("garching"), None);
+    }
+
+    #[test]
+    fn windows_are_resolved_in_local_time() {
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 22, 30, 12).unwrap();
+        assert_eq!(
+            FreeWindow::Now.resolve(now),
+            (
+                Utc.with_ymd_and_hms(2024, 7, 1, 22, 30, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 7, 1, 23, 30, 0).unwrap()
+            )
+        );
+        // it is already the 2nd of July in Berlin (CEST is UTC+2)
+        let window = FreeWindow::parse("09:00-16:30").unwrap();
+        assert_eq!(
+            window.resolve(now),
+            (
+                Utc.with_ymd_and_hms(2024, 7, 2, 7, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 7, 2, 14, 30, 0).unwrap()
+            )
+        );
+        // 02:30 does not exist on the day summer time starts
+        let now = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+        let window = FreeWindow::parse("02:30-04:00").unwrap();
+        assert_eq!(
+            window.resolve(now),
+            (
+                Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 3, 31, 2, 0, 0).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn only_the_windows_and_scrape_identify_unavailable_rooms() {
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+        let rooms = |windows, last_scrape_at, keys: &[&str]| UnavailableRooms {
+            windows,
+            last_scrape_at,
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+        };
+        let window = vec![FreeWindow::Now.resolve(now)];
+        let later_window = vec![FreeWindow::Now.resolve(now + Duration::minutes(1))];
+        let scrape = Some(now - Duration::minutes(5));
+        let later_scrape = Some(now);
+        assert_eq!(
+            rooms(window.clone(), scrape, &["5602.EG.001"]).cache_key(),
+            rooms(window.clone(), scrape, &["5602.EG.001", "5602.EG.002"]).cache_key()
+        );
+        assert_ne!(
+            rooms(window.clone(), scrape, &[]).cache_key(),
+            rooms(later_window, scrape, &[]).cache_key()
+        );
+        assert_ne!(
+            rooms(window.clone(), scrape, &[]).cache_key(),
+            rooms(window, later_scrape, &[]).cache_key()
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use logos::Logos;
+use meilisearch_sdk::client::Client;
+use meilisearch_sdk::search::SearchResult;
+use serde::Serialize;
+
+use super::lexer::Token;
+use super::parser::ParsedQuery;
+use crate::external::meilisearch::{MSHit, SubQuery};
+use crate::routes::search::{Highlighting, Limits};
+
+#[expect(
+    unused_imports,
+    reason = "has to be imported as otherwise utoipa generates incorrect code"
+)]
+use serde_json::json;
+
+/// How a query was interpreted and what meilisearch made of it
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct SearchExplanation {
+    /// The tokens the lexer split the query into
+    ///
+    /// Parts of the query which could not be lexed are omitted.
+    #[schema(
+        value_type = Vec<Object>,
+        example = json!([{"text": "wc"}, {"parent_filter": "garching"}])
+    )]
+    tokens: Vec<Token>,
+    /// The query after parsing the tokens into texts, filters and sorting
+    #[schema(
+        value_type = Object,
+        example = json!({"tokens": [{"text": "wc"}], "filters": {"parents": ["garching"]}, "sorting": {"location": []}})
+    )]
+    parsed: ParsedQuery,
+    /// What was sent to meilisearch for each sub-query and what it returned
+    sub_queries: Vec<SubQueryExplanation>,
+}
+
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct SubQueryExplanation {
+    /// What was sent to meilisearch
+    query: SubQuery,
+    /// The hits of this sub-query, before they are merged into sections
+    hits: Vec<HitExplanation>,
+}
+
+/// Why a hit was ranked where it was
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HitExplanation {
+    /// The id of the location
+    #[schema(example = "5510.02.001")]
+    id: String,
+    /// The ranking score from `0.0` (worst) to `1.0` (best)
+    #[schema(example = 0.93)]
+    ranking_score: Option<f64>,
+    /// The score of each ranking rule (e.g. `words`, `typo` or `proximity`), as computed by meilisearch
+    #[schema(value_type = Option<Object>)]
+    ranking_score_details: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl From<&SearchResult<MSHit>> for HitExplanation {
+    fn from(hit: &SearchResult<MSHit>) -> Self {
+        Self {
+            id: hit.result.room_code.clone(),
+            ranking_score: hit.ranking_score,
+            ranking_score_details: hit.ranking_score_details.clone(),
+        }
+    }
+}
+
+/// Executes the (non-paginated) search for `q` again, recording every intermediate step
+#[tracing::instrument(skip(client, unavailable_rooms))]
+pub(super) async fn explain(
+    client: &Client,
+    q: &str,
+    highlighting: Highlighting,
+    limits: Limits,
+    unavailable_rooms: &[String],
+) -> Result<SearchExplanation, meilisearch_sdk::errors::Error> {
+    let tokens = Token::lexer(q).filter_map(Result::ok).collect();
+    let parsed = ParsedQuery::from(q);
+    let mut query =
+        super::geoentry_query(client, &parsed, &limits, &highlighting, unavailable_rooms);
+    query.with_ranking_score_details();
+    let sub_queries = query.sub_queries();
+    let response = query.execute().await?;
+    let sub_queries = sub_queries
+        .into_iter()
+        .zip(response.results.iter())
+        .map(|(query, results)| SubQueryExplanation {
+            query,
+            hits: results.hits.iter().map(HitExplanation::from).collect(),
+        })
+        .collect();
+    Ok(SearchExplanation {
+        tokens,
+        parsed,
+        sub_queries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn tokens_and_parsed_query_are_serialised() {
+        let tokens = Token::lexer("wc in:garching")
+            .filter_map(Result::ok)
+            .collect::<Vec<Token>>();
+        assert_eq!(
+            serde_json::to_value(tokens).unwrap(),
+            json!([{"text": "wc"}, {"parent_filter": "garching"}])
+        );
+        let parsed = serde_json::to_value(ParsedQuery::from("mw2001 near:48.26,11.67")).unwrap();
+        assert_eq!(
+            parsed["tokens"],
+            json!([{"splittable_text": ["mw", "2001"]}])
+        );
+        assert_eq!(parsed["sorting"], json!({"location": ["48.26,11.67"]}));
+    }
+}
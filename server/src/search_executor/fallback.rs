@@ -0,0 +1,271 @@
+use sqlx::PgPool;
+
+use super::parser::{ParsedQuery, TextToken};
+use super::{ResultEntry, ResultFacet, ResultsSection};
+use crate::db::location::SimilarLocation;
+use crate::limited::vec::LimitedVec;
+use crate::routes::search::Limits;
+
+/// Searches the names of all entries in postgres, instead of meilisearch
+///
+/// In contrast to [`super::do_geoentry_search`], filters, sorting and highlighting are not supported.
+#[tracing::instrument(skip(pool))]
+pub(super) async fn search(
+    pool: &PgPool,
+    parsed_input: &ParsedQuery,
+    limits: Limits,
+    should_use_english: bool,
+) -> sqlx::Result<LimitedVec<ResultsSection>> {
+    let query = as_similarity_query(parsed_input);
+    if query.is_empty() {
+        return Ok(LimitedVec(vec![]));
+    }
+    let limit = limits.buildings_count + limits.rooms_count;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let similar = SimilarLocation::fetch_similar(pool, &query, should_use_english, limit).await?;
+    let (buildings, rooms): (Vec<SimilarLocation>, Vec<SimilarLocation>) = similar
+        .into_iter()
+        .partition(|l| is_site_or_building(&l.r#type));
+
+    let buildings = buildings
+        .into_iter()
+        .take(limits.buildings_count)
+        .map(|l| ResultEntry {
+            id: l.key,
+            r#type: l.r#type,
+            name: l.name,
+            subtext: l.type_common_name,
+            ..ResultEntry::default()
+        })
+        .collect::<Vec<ResultEntry>>();
+    let rooms = rooms
+        .into_iter()
+        .take(limits.rooms_count)
+        .map(|l| ResultEntry {
+            id: l.key,
+            r#type: l.r#type,
+            name: l.name,
+            subtext: l.parent_name.unwrap_or(l.type_common_name),
+            ..ResultEntry::default()
+        })
+        .collect::<Vec<ResultEntry>>();
+
+    // the total limit only counts visible results
+    let buildings_visible = buildings.len().min(limits.total_count);
+    let rooms_visible = rooms.len().min(limits.total_count - buildings_visible);
+    let section_buildings = section(ResultFacet::SitesBuildings, buildings, buildings_visible);
+    let section_rooms = section(ResultFacet::Rooms, rooms, rooms_visible);
+    Ok(match section_buildings.n_visible {
+        0 => LimitedVec(vec![section_rooms, section_buildings]),
+        _ => LimitedVec(vec![section_buildings, section_rooms]),
+    })
+}
+
+fn section(facet: ResultFacet, entries: Vec<ResultEntry>, n_visible: usize) -> ResultsSection {
+    ResultsSection {
+        facet,
+        estimated_total_hits: entries.len(),
+        entries,
+        n_visible,
+        next_offset: None,
+    }
+}
+
+fn is_site_or_building(r#type: &str) -> bool {
+    matches!(
+        r#type,
+        "campus" | "site" | "area" | "building" | "joined_building"
+    )
+}
+
+/// The text of the query without any filters, as postgres can't apply them
+fn as_similarity_query(parsed_input: &ParsedQuery) -> String {
+    parsed_input
+        .tokens
+        .iter()
+        .map(|token| match token {
+            TextToken::Text(t) => t.clone(),
+            TextToken::SplittableText((t1, t2)) => format!("{t1} {t2}"),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn filters_are_not_part_of_the_query() {
+        let parsed = ParsedQuery::from("interims in:garching usage:hörsaal");
+        assert_eq!(as_similarity_query(&parsed), "interims");
+        let parsed = ParsedQuery::from("usage:wc");
+        assert_eq!(as_similarity_query(&parsed), "");
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::setup::tests::PostgresTestContainer;
+
+    fn location(name: &str, r#type: &str, type_common_name: &str, parents: &[&str]) -> String {
+        json!({
+            "name": name,
+            "type": r#type,
+            "type_common_name": type_common_name,
+            "coords": {"lat": 48.265, "lon": 11.671, "source": "navigatum"},
+            "parent_names": parents,
+        })
+        .to_string()
+    }
+
+    async fn load_sample_data(pool: &PgPool) {
+        let de_building = ["Standorte", "Garching Forschungszentrum"];
+        let de_room = [
+            "Standorte",
+            "Garching Forschungszentrum",
+            "Interimshörsaal 1 (IHS 1)",
+        ];
+        let en_building = ["Sites", "Garching Research Centre"];
+        let en_room = [
+            "Sites",
+            "Garching Research Centre",
+            "Interim lecture hall 1 (IHS 1)",
+        ];
+        let entries = [
+            (
+                "5620",
+                location(
+                    "Interimshörsaal 1 (IHS 1)",
+                    "building",
+                    "Gebäude",
+                    &de_building,
+                ),
+                location(
+                    "Interim lecture hall 1 (IHS 1)",
+                    "building",
+                    "Building",
+                    &en_building,
+                ),
+            ),
+            (
+                "5620.01.101",
+                location(
+                    "5620.01.101 (Interimshörsaal 1)",
+                    "room",
+                    "Hörsaal",
+                    &de_room,
+                ),
+                location(
+                    "5620.01.101 (Interim lecture hall 1)",
+                    "room",
+                    "Lecture hall",
+                    &en_room,
+                ),
+            ),
+            (
+                "5620.01.102",
+                location("5620.01.102 (Seminarraum)", "room", "Seminarraum", &de_room),
+                location(
+                    "5620.01.102 (Seminar room)",
+                    "room",
+                    "Seminar room",
+                    &en_room,
+                ),
+            ),
+        ];
+        for (key, de, en) in entries {
+            for (lang, data) in [("de", de), ("en", en)] {
+                let query = format!("INSERT INTO {lang}(key,data) VALUES ($1,$2::jsonb)");
+                sqlx::query(&query)
+                    .bind(key)
+                    .bind(data)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    async fn search_for(pool: &PgPool, q: &str, should_use_english: bool) -> Vec<ResultsSection> {
+        let parsed = ParsedQuery::from(q);
+        search(pool, &parsed, Limits::default(), should_use_english)
+            .await
+            .unwrap()
+            .0
+    }
+
+    fn ids(section: &ResultsSection) -> Vec<&str> {
+        section.entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn misspelled_names_are_found() {
+        let pg = PostgresTestContainer::new().await;
+        load_sample_data(&pg.pool).await;
+
+        let sections = search_for(&pg.pool, "interimshorsaal", false).await;
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].facet, ResultFacet::SitesBuildings);
+        assert_eq!(ids(&sections[0]), vec!["5620"]);
+        assert_eq!(sections[0].n_visible, 1);
+        assert_eq!(sections[1].facet, ResultFacet::Rooms);
+        assert_eq!(ids(&sections[1]), vec!["5620.01.101"]);
+        assert_eq!(sections[1].entries[0].subtext, "Interimshörsaal 1 (IHS 1)");
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn english_names_are_found_and_returned() {
+        let pg = PostgresTestContainer::new().await;
+        load_sample_data(&pg.pool).await;
+
+        let sections = search_for(&pg.pool, "interim lecture hal", true).await;
+        assert_eq!(ids(&sections[0]), vec!["5620"]);
+        assert_eq!(
+            sections[0].entries[0].name,
+            "Interim lecture hall 1 (IHS 1)"
+        );
+        assert_eq!(sections[0].entries[0].subtext, "Building");
+        assert_eq!(ids(&sections[1]), vec!["5620.01.101"]);
+        assert_eq!(
+            sections[1].entries[0].subtext,
+            "Interim lecture hall 1 (IHS 1)"
+        );
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn rooms_come_first_without_matching_buildings() {
+        let pg = PostgresTestContainer::new().await;
+        load_sample_data(&pg.pool).await;
+
+        let sections = search_for(&pg.pool, "seminarraum", false).await;
+        assert_eq!(sections[0].facet, ResultFacet::Rooms);
+        assert_eq!(ids(&sections[0]), vec!["5620.01.102"]);
+        assert_eq!(sections[1].facet, ResultFacet::SitesBuildings);
+        assert_eq!(sections[1].n_visible, 0);
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn like_wildcards_are_matched_literally() {
+        let pg = PostgresTestContainer::new().await;
+        load_sample_data(&pg.pool).await;
+
+        for q in ["_", "%", "\\"] {
+            let similar = SimilarLocation::fetch_similar(&pg.pool, q, false, 10)
+                .await
+                .unwrap();
+            let keys = similar.iter().map(|l| l.key.as_str()).collect::<Vec<_>>();
+            assert_eq!(keys, Vec::<&str>::new(), "{q} should match nothing");
+        }
+    }
+}
@@ -8,14 +8,17 @@ use crate::search::Highlighting;
 pub(super) struct RoomVisitor {
     parsed_input: ParsedQuery,
     highlighting: Highlighting,
+    subtext_highlighter: SubtextHighlighter,
 }
 
 impl From<(ParsedQuery, Highlighting)> for RoomVisitor {
     #[tracing::instrument]
     fn from((parsed_input, highlighting): (ParsedQuery, Highlighting)) -> Self {
+        let subtext_highlighter = SubtextHighlighter::from((&parsed_input, highlighting.clone()));
         Self {
             parsed_input,
             highlighting,
+            subtext_highlighter,
         }
     }
 }
@@ -23,7 +26,7 @@ impl From<(ParsedQuery, Highlighting)> for RoomVisitor {
 impl RoomVisitor {
     pub(super) fn visit(&self, item: &mut ResultEntry) {
         item.parsed_id = self.parse_room_formats(&item.hit);
-        item.subtext = Self::generate_subtext(&item.hit);
+        item.subtext = self.generate_subtext(&item.hit);
     }
     // Parse the search against some known room formats and improve the
     // results display in this case. Room formats are hardcoded for now.
@@ -132,19 +135,110 @@ impl RoomVisitor {
         }
     }
 
-    fn generate_subtext(hit: &MSHit) -> String {
+    fn generate_subtext(&self, hit: &MSHit) -> String {
         let building = match hit.parent_building_names.len() {
             0 => String::new(),
-            _ => hit.parent_building_names[0].clone(),
+            _ => self
+                .subtext_highlighter
+                .highlight(&hit.parent_building_names[0]),
         };
 
         match &hit.campus {
-            Some(campus) => format!("{campus}, {building}"),
+            Some(campus) => {
+                let campus = self.subtext_highlighter.highlight(campus);
+                format!("{campus}, {building}")
+            }
             None => building,
         }
     }
 }
 
+/// Highlights the words of subtexts, which start with a term of the query or one of its parent filters
+///
+/// In contrast to the name, the subtext is not highlighted by meilisearch.
+/// Meilisearch would also not highlight filters like `@garching`, which are the usual reason for a subtext to be relevant.
+pub(super) struct SubtextHighlighter {
+    /// lowercase terms, the longest first
+    terms: Vec<String>,
+    highlighting: Highlighting,
+}
+
+/// Shorter terms would highlight too much (e.g. every `a`)
+const MIN_HIGHLIGHT_TERM_LEN: usize = 2;
+
+impl From<(&ParsedQuery, Highlighting)> for SubtextHighlighter {
+    fn from((parsed_input, highlighting): (&ParsedQuery, Highlighting)) -> Self {
+        let mut terms = Vec::new();
+        for token in &parsed_input.tokens {
+            match token {
+                TextToken::Text(t) => terms.push(t.to_lowercase()),
+                TextToken::SplittableText((t1, t2)) => {
+                    terms.push(t1.to_lowercase());
+                    terms.push(t2.to_lowercase());
+                }
+            }
+        }
+        for parent in parsed_input.filters.parents() {
+            // parent filters are slugified, e.g. `garching-hochbrueck`
+            terms.push(parent.to_lowercase());
+            terms.push(parent.to_lowercase().replace('-', " "));
+        }
+        terms.retain(|t| t.chars().count() >= MIN_HIGHLIGHT_TERM_LEN);
+        terms.sort_by_key(|t| std::cmp::Reverse(t.chars().count()));
+        terms.dedup();
+        Self {
+            terms,
+            highlighting,
+        }
+    }
+}
+
+impl SubtextHighlighter {
+    pub(super) fn highlight(&self, text: &str) -> String {
+        let highlighting_disabled =
+            self.highlighting.pre.is_empty() && self.highlighting.post.is_empty();
+        if highlighting_disabled || self.terms.is_empty() {
+            return text.to_string();
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut copied_until = 0;
+        let mut previous_char: Option<char> = None;
+        for (start, c) in text.char_indices() {
+            let is_word_start = previous_char.is_none_or(|p| !p.is_alphanumeric());
+            previous_char = Some(c);
+            if start < copied_until || !is_word_start {
+                continue;
+            }
+            let Some(end) = self
+                .terms
+                .iter()
+                .find_map(|term| matched_until(text, start, term))
+            else {
+                continue;
+            };
+            result.push_str(&text[copied_until..start]);
+            result.push_str(&self.highlighting.pre);
+            result.push_str(&text[start..end]);
+            result.push_str(&self.highlighting.post);
+            copied_until = end;
+        }
+        result.push_str(&text[copied_until..]);
+        result
+    }
+}
+
+/// Where the `term` ends, if the `text` continues with it at `start` (ignoring the case)
+fn matched_until(text: &str, start: usize, term: &str) -> Option<usize> {
+    let mut chars = text[start..].char_indices();
+    for expected in term.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(text.len(), |(i, _)| start + i))
+}
+
 fn unicode_split_at(search: &str, width: usize) -> (&str, &str) {
     // since some UTF-8 grapheme clusters are more than one byte, we need to check where we can split
     let splitpoint = search.chars().take(width).collect::<String>().len();
@@ -167,4 +261,43 @@ mod tests {
         assert_eq!(unicode_split_at("Ähh", 0), ("", "Ähh"));
         assert_eq!(unicode_split_at("Ähh", 1), ("Ä", "hh"));
     }
+
+    #[test]
+    fn subtext_highlighting() {
+        let highlighting = Highlighting {
+            pre: "<".to_string(),
+            post: ">".to_string(),
+        };
+        let parsed = ParsedQuery::from("hs1 @garching-hochbrueck");
+        let highlighter = SubtextHighlighter::from((&parsed, highlighting.clone()));
+        assert_eq!(
+            highlighter.highlight("Garching Hochbrück, Garching-Hochbrueck"),
+            "Garching Hochbrück, <Garching-Hochbrueck>"
+        );
+        let parsed = ParsedQuery::from("mw2001 @garching");
+        let highlighter = SubtextHighlighter::from((&parsed, highlighting.clone()));
+        assert_eq!(
+            highlighter.highlight("Maschinenwesen (MW)"),
+            "Maschinenwesen (<MW>)"
+        );
+        assert_eq!(highlighter.highlight("garching"), "<garching>");
+        // only the starts of words are highlighted
+        let parsed = ParsedQuery::from("mi");
+        let highlighter = SubtextHighlighter::from((&parsed, highlighting));
+        assert_eq!(highlighter.highlight("Chemie"), "Chemie");
+        assert_eq!(
+            highlighter.highlight("Mathematik / Informatik (MI)"),
+            "Mathematik / Informatik (<MI>)"
+        );
+    }
+
+    #[test]
+    fn subtext_highlighting_can_be_disabled() {
+        let highlighting = Highlighting {
+            pre: String::new(),
+            post: String::new(),
+        };
+        let highlighter = SubtextHighlighter::from((&ParsedQuery::from("@garching"), highlighting));
+        assert_eq!(highlighter.highlight("garching"), "garching");
+    }
 }
@@ -1,5 +1,6 @@
 use logos::{Lexer, Logos};
 use regex::Regex;
+use serde::Serialize;
 
 /// An irregular split is defined as at least a letter and 1-4 numbers
 /// Treating words like MW1801 differently has improvements in relevancy for room-level searches
@@ -23,6 +24,22 @@ fn remove_prefix(lex: &mut Lexer<Token>, prefix: &'static str) -> String {
     lex.slice()[prefix.len()..].trim().to_string()
 }
 
+/// Removes the negation (`-`, `!` or `not:`) and the specified prefixes from the token
+/// e.g. used to remove "-in:" or "not:usage:" from negated filters
+fn remove_negated_prefix(lex: &mut Lexer<Token>, prefixes: &[&'static str]) -> String {
+    let slice = lex.slice();
+    let slice = slice
+        .strip_prefix("not:")
+        .or_else(|| slice.strip_prefix('-'))
+        .or_else(|| slice.strip_prefix('!'))
+        .unwrap_or(slice);
+    let slice = prefixes
+        .iter()
+        .find_map(|prefix| slice.strip_prefix(prefix))
+        .unwrap_or(slice);
+    slice.trim().to_string()
+}
+
 /// Removes non-ascii characters from the token (replacing them with at most one whitespace)
 fn slugify<S: Into<String>>(input: S) -> String {
     let slugify_regex = Regex::new(r"[^a-zA-Z0-9-äöüß.]+").unwrap();
@@ -33,13 +50,88 @@ fn slugify<S: Into<String>>(input: S) -> String {
     slug.trim_matches('-').to_string()
 }
 
+/// Types are stored with underscores in the index (e.g. `joined_building`)
+/// => both `joined-building` and `joined_building` should be accepted
+fn slugify_type<S: Into<String>>(input: S) -> String {
+    slugify(input).replace('-', "_")
+}
+
+/// Normalises the different ways to write a floor to how floors are written in room codes
+/// e.g. `OG1`, `1` and `01` are all `01`, `UG` and `-1` are `U1` and `0` is `EG`
+fn normalise_floor<S: Into<String>>(input: S) -> String {
+    let floor = input.into().to_uppercase().replace('.', "");
+    let number = |digits: &str| digits.parse::<u8>().ok();
+    match floor.as_str() {
+        "EG" => return floor,
+        "UG" => return "U1".to_string(),
+        _ => {}
+    }
+    let basement = ["UG", "U", "-"]
+        .iter()
+        .find_map(|prefix| floor.strip_prefix(prefix).and_then(number));
+    if let Some(n) = basement {
+        return format!("U{n}");
+    }
+    let upper = floor
+        .strip_prefix("OG")
+        .or_else(|| floor.strip_suffix("OG"))
+        .unwrap_or(&floor);
+    match number(upper) {
+        Some(0) => "EG".to_string(),
+        Some(n) => format!("{n:02}"),
+        None => floor,
+    }
+}
+
+/// Normalises a time window to either `now` or `HH:MM-HH:MM`, rejecting impossible times and empty windows
+/// e.g. `jetzt` is `now` and `9:00-9:30` is `09:00-09:30`
+fn free_window(lex: &mut Lexer<Token>, prefix: &'static str) -> Option<String> {
+    let window = remove_prefix(lex, prefix).to_lowercase();
+    if window == "now" || window == "jetzt" {
+        return Some("now".to_string());
+    }
+    let minutes = |time: &str| {
+        let (hours, minutes) = time.split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    };
+    let (start, end) = window.split_once('-')?;
+    let (start, end) = (minutes(start)?, minutes(end)?);
+    (start < end).then(|| {
+        format!(
+            "{:02}:{:02}-{:02}:{:02}",
+            start / 60,
+            start % 60,
+            end / 60,
+            end % 60
+        )
+    })
+}
+
+/// Normalises the corners of a bounding box to `(top_right, bottom_left)`, which is the order meilisearch expects.
+/// Any two opposite corners are accepted.
+fn bounding_box(lex: &mut Lexer<Token>) -> Option<(String, String)> {
+    let coords = remove_prefix(lex, "bbox:")
+        .split(',')
+        .map(|c| c.parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    let [lat1, lon1, lat2, lon2] = coords[..] else {
+        return None;
+    };
+    Some((
+        format!("{},{}", lat1.max(lat2), lon1.max(lon2)),
+        format!("{},{}", lat1.min(lat2), lon1.min(lon2)),
+    ))
+}
+
 /// Parses the query string into a list of tokens
 /// priority between tokens is set as follows
-/// 1. Filters (`ParentFilter`,`UsageFilter`,`TypeFilter`) / quoted `Text` / `LocationSort`
+/// 1. (negated) Filters (`ParentFilter`,`UsageFilter`,`TypeFilter`), `FloorFilter`, `OperatorFilter`, `FreeFilter`, geo filters / quoted `Text` / `LocationSort`
 /// 2. `SplittableText`
 /// 3. `Text`
 /// 4. skip
-#[derive(Logos, Debug, PartialEq, Clone)]
+#[derive(Logos, Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[logos(skip r"[ \t\n\f]+")]
 pub enum Token {
     #[regex("\"[^\"]+\"", | lex | lex.slice()[1..lex.slice().len() - 1].to_string(), priority = 3)]
@@ -56,14 +148,46 @@ pub enum Token {
     #[regex("near: ?-?[0-9]+[.][0-9.]+,-?[0-9]+[.][0-9.]+", | lex | remove_prefix(lex, "near:"), priority = 3)]
     LocationSort(String), // e.g. near:lat,lon
 
+    #[regex("within: ?-?[0-9]+[.][0-9]+,-?[0-9]+[.][0-9]+,[0-9]+([.][0-9]+)?", | lex | remove_prefix(lex, "within:"), priority = 3)]
+    GeoRadiusFilter(String), // e.g. within:lat,lon,radius_m
+
+    #[regex(
+        "bbox: ?-?[0-9]+[.][0-9]+,-?[0-9]+[.][0-9]+,-?[0-9]+[.][0-9]+,-?[0-9]+[.][0-9]+",
+        bounding_box,
+        priority = 3
+    )]
+    GeoBoundingBoxFilter((String, String)), // (top_right, bottom_left) as lat,lon
+
     #[regex("usage: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "usage:")), priority = 3)]
     #[regex("nutzung: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "nutzung:")), priority = 3)]
     #[regex("= ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "=")), priority = 3)]
     UsageFilter(String),
 
-    #[regex("type: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "type:")), priority = 3)]
-    #[regex("typ: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "typ:")), priority = 3)]
+    #[regex("type: ?[a-zA-Z0-9-äöüß._]+", | lex | slugify_type(remove_prefix(lex, "type:")), priority = 3)]
+    #[regex("typ: ?[a-zA-Z0-9-äöüß._]+", | lex | slugify_type(remove_prefix(lex, "typ:")), priority = 3)]
     TypeFilter(String),
+
+    #[regex("floor: ?-?[a-zA-Z0-9.]+", | lex | normalise_floor(remove_prefix(lex, "floor:")), priority = 3)]
+    #[regex("etage: ?-?[a-zA-Z0-9.]+", | lex | normalise_floor(remove_prefix(lex, "etage:")), priority = 3)]
+    #[regex("level: ?-?[a-zA-Z0-9.]+", | lex | normalise_floor(remove_prefix(lex, "level:")), priority = 3)]
+    FloorFilter(String),
+
+    #[regex("operator: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "operator:")), priority = 3)]
+    #[regex("org: ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_prefix(lex, "org:")), priority = 3)]
+    OperatorFilter(String), // the code (e.g. TUS7000) or id (e.g. 51901) of the operator
+
+    #[regex("free: ?(now|jetzt|[0-9]{1,2}:[0-9]{2}-[0-9]{1,2}:[0-9]{2})", | lex | free_window(lex, "free:"), priority = 3)]
+    #[regex("frei: ?(now|jetzt|[0-9]{1,2}:[0-9]{2}-[0-9]{1,2}:[0-9]{2})", | lex | free_window(lex, "frei:"), priority = 3)]
+    FreeFilter(String), // `now` or `HH:MM-HH:MM`
+
+    #[regex("(-|!|not:)(in:|@) ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_negated_prefix(lex, &["in:", "@"])), priority = 3)]
+    NegatedParentFilter(String),
+
+    #[regex("(-|!|not:)(usage:|nutzung:|=) ?[a-zA-Z0-9-äöüß.]+", | lex | slugify(remove_negated_prefix(lex, &["usage:", "nutzung:", "="])), priority = 3)]
+    NegatedUsageFilter(String),
+
+    #[regex("(-|!|not:)(type:|typ:) ?[a-zA-Z0-9-äöüß._]+", | lex | slugify_type(remove_negated_prefix(lex, &["type:", "typ:"])), priority = 3)]
+    NegatedTypeFilter(String),
 }
 
 #[cfg(test)]
@@ -239,6 +363,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn negated_filters() {
+        let matchings = [
+            (
+                vec!["in:", "@"],
+                Token::NegatedParentFilter("foo".to_string()),
+            ),
+            (
+                vec!["usage:", "nutzung:", "="],
+                Token::NegatedUsageFilter("foo".to_string()),
+            ),
+            (
+                vec!["type:", "typ:"],
+                Token::NegatedTypeFilter("foo".to_string()),
+            ),
+        ];
+
+        for (filters, expected) in matchings {
+            for filter in filters {
+                for negation in ["-", "!", "not:"] {
+                    for sep in ["", " "] {
+                        let text = format!("{negation}{filter}{sep}foo");
+                        let mut lexer = Token::lexer(&text);
+                        assert_eq!(lexer.next(), Some(Ok(expected.clone())));
+                        assert_eq!(lexer.next(), None);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negated_filters_keep_dashes_in_values() {
+        let mut lexer = Token::lexer("-in:garching-hochbrueck");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token::NegatedParentFilter(
+                "garching-hochbrueck".to_string()
+            )))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn type_filters_with_underscores() {
+        for text in [
+            "type:virtual_room",
+            "type:virtual-room",
+            "typ: virtual_room",
+        ] {
+            let mut lexer = Token::lexer(text);
+            assert_eq!(
+                lexer.next(),
+                Some(Ok(Token::TypeFilter("virtual_room".to_string())))
+            );
+            assert_eq!(lexer.next(), None);
+        }
+        let mut lexer = Token::lexer("!type:joined_building");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token::NegatedTypeFilter("joined_building".to_string())))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
     #[test]
     fn sortings() {
         for sep in ["", " "] {
@@ -251,4 +440,97 @@ mod tests {
             assert_eq!(lexer.next(), None);
         }
     }
+
+    #[test]
+    fn floor_filters() {
+        for (text, expected) in [
+            ("floor:EG", "EG"),
+            ("floor:eg", "EG"),
+            ("floor:0", "EG"),
+            ("etage:1", "01"),
+            ("etage: 01", "01"),
+            ("level:OG1", "01"),
+            ("level:1.OG", "01"),
+            ("floor:og12", "12"),
+            ("floor:UG", "U1"),
+            ("floor:U1", "U1"),
+            ("floor:UG2", "U2"),
+            ("floor:-1", "U1"),
+            ("floor:DG", "DG"),
+            ("floor:Z1", "Z1"),
+        ] {
+            let mut lexer = Token::lexer(text);
+            assert_eq!(
+                lexer.next(),
+                Some(Ok(Token::FloorFilter(expected.to_string()))),
+                "{text}"
+            );
+            assert_eq!(lexer.next(), None);
+        }
+    }
+
+    #[test]
+    fn operator_filters() {
+        for text in ["operator:TUS7000", "org:tus7000", "operator: TUS7000"] {
+            let mut lexer = Token::lexer(text);
+            assert_eq!(
+                lexer.next(),
+                Some(Ok(Token::OperatorFilter("tus7000".to_string()))),
+                "{text}"
+            );
+            assert_eq!(lexer.next(), None);
+        }
+        let mut lexer = Token::lexer("wc org:51901");
+        assert_eq!(lexer.next(), Some(Ok(Token::Text("wc".to_string()))));
+        assert_eq!(
+            lexer.next(),
+            Some(Ok(Token::OperatorFilter("51901".to_string())))
+        );
+    }
+
+    #[test]
+    fn free_filters() {
+        for (text, expected) in [
+            ("free:now", "now"),
+            ("frei:jetzt", "now"),
+            ("free: now", "now"),
+            ("free:14:00-16:00", "14:00-16:00"),
+            ("frei:9:00-9:30", "09:00-09:30"),
+        ] {
+            let mut lexer = Token::lexer(text);
+            assert_eq!(
+                lexer.next(),
+                Some(Ok(Token::FreeFilter(expected.to_string()))),
+                "{text}"
+            );
+            assert_eq!(lexer.next(), None, "{text}");
+        }
+        for text in ["free:16:00-14:00", "free:25:00-26:00", "free:14:60-16:00"] {
+            assert_eq!(Token::lexer(text).next(), Some(Err(())), "{text}");
+        }
+    }
+
+    #[test]
+    fn geo_filters() {
+        for sep in ["", " "] {
+            let text = format!("within:{sep}48.262,11.668,500");
+            let mut lexer = Token::lexer(&text);
+            assert_eq!(
+                lexer.next(),
+                Some(Ok(Token::GeoRadiusFilter("48.262,11.668,500".to_string())))
+            );
+            assert_eq!(lexer.next(), None);
+        }
+        let expected =
+            Token::GeoBoundingBoxFilter(("48.27,11.68".to_string(), "48.26,11.66".to_string()));
+        for text in [
+            "bbox:48.27,11.68,48.26,11.66",
+            "bbox:48.26,11.66,48.27,11.68",
+            "bbox: 48.26,11.68,48.27,11.66",
+        ] {
+            let mut lexer = Token::lexer(text);
+            assert_eq!(lexer.next(), Some(Ok(expected.clone())), "{text}");
+            assert_eq!(lexer.next(), None);
+        }
+    }
 }
@@ -2,7 +2,7 @@ use meilisearch_sdk::search::{SearchResult, SearchResults};
 
 use super::ResultFacet;
 use crate::external::meilisearch::MSHit;
-use crate::routes::search::Limits;
+use crate::routes::search::{Limits, Page, PaginatedFacet};
 
 #[tracing::instrument(skip(merged_results, buildings_results, rooms_results))]
 pub(super) fn merge_search_results(
@@ -13,89 +13,216 @@ pub(super) fn merge_search_results(
 ) -> (super::ResultsSection, super::ResultsSection) {
     // First look up which buildings did match even with a closed query.
     // We can consider them more relevant.
-    // TODO: This has to be implemented. closed_matching_buildings is not used further down in this function.
-    let mut closed_matching_buildings = Vec::<String>::new();
-    for hit in &buildings_results.hits {
-        closed_matching_buildings.push(hit.result.room_code.clone());
-    }
+    let closed_matching_buildings = buildings_results
+        .hits
+        .iter()
+        .map(|hit| hit.result.room_code.clone())
+        .collect::<Vec<String>>();
 
     let mut section_buildings = super::ResultsSection {
         facet: ResultFacet::SitesBuildings,
         entries: Vec::new(),
         n_visible: 0,
         estimated_total_hits: buildings_results.estimated_total_hits.unwrap_or(0),
+        next_offset: None,
     };
     let mut section_rooms = super::ResultsSection {
         facet: ResultFacet::Rooms,
         entries: Vec::new(),
         n_visible: 0,
         estimated_total_hits: rooms_results.estimated_total_hits.unwrap_or(0),
+        next_offset: None,
     };
 
-    // TODO: Collapse joined buildings
-    // let mut observed_joined_buildings = Vec::<String>::new();
+    // ids of the buildings, which were visible when the first room was added
+    let mut visible_building_ids = Vec::<String>::new();
     let mut observed_ids = Vec::<String>::new();
-    for hits in [&merged_results.hits, &rooms_results.hits] {
-        for hit in hits {
-            // Prevent duplicates from being added to the results
-            if observed_ids.contains(&hit.result.room_code) {
-                continue;
-            };
-            observed_ids.push(hit.result.room_code.clone());
-
-            // Total limit reached (does only count visible results)
-            let current_buildings_cnt = if section_buildings.n_visible == 0 {
-                section_buildings.entries.len()
-            } else {
-                section_buildings.n_visible
-            };
-            if section_rooms.entries.len() + current_buildings_cnt >= limits.total_count {
-                break;
+    let hits = merged_results.hits.iter().chain(rooms_results.hits.iter());
+    let hits = prefer_rooms_in_buildings(&closed_matching_buildings, hits.collect());
+    for hit in hits {
+        // Prevent duplicates from being added to the results
+        if observed_ids.contains(&hit.result.room_code) {
+            continue;
+        };
+        observed_ids.push(hit.result.room_code.clone());
+
+        // Total limit reached (does only count visible results)
+        let current_buildings_cnt = if section_buildings.n_visible == 0 {
+            section_buildings.entries.len()
+        } else {
+            section_buildings.n_visible
+        };
+        if section_rooms.entries.len() + current_buildings_cnt >= limits.total_count {
+            break;
+        }
+        let formatted_name = extract_formatted_name(hit).unwrap_or_else(|| hit.result.name.clone());
+
+        let hit = hit.result.clone();
+        match hit.r#type.as_str() {
+            "campus" | "site" | "area" | "building" | "joined_building" => {
+                let entry = building_entry(hit, formatted_name);
+                collapse_into_buildings(&mut section_buildings, entry, limits);
             }
-            let formatted_name =
-                extract_formatted_name(hit).unwrap_or_else(|| hit.result.name.clone());
+            "room" | "virtual_room" => {
+                if section_rooms.entries.len() < limits.rooms_count {
+                    section_rooms.entries.push(room_entry(hit, formatted_name));
 
-            let hit = hit.result.clone();
-            match hit.r#type.as_str() {
-                "campus" | "site" | "area" | "building" | "joined_building" => {
-                    if section_buildings.entries.len() < limits.buildings_count {
-                        section_buildings.entries.push(super::ResultEntry {
-                            hit: hit.clone(),
-                            id: hit.room_code.to_string(),
-                            r#type: hit.r#type,
-                            name: formatted_name,
-                            subtext: hit.type_common_name,
-                            subtext_bold: None,
-                            parsed_id: None,
-                        });
+                    // The first room in the results 'freezes' the number of visible buildings
+                    if section_buildings.n_visible == 0 && section_rooms.entries.len() == 1 {
+                        section_buildings.n_visible = section_buildings.entries.len();
+                        visible_building_ids = section_buildings
+                            .entries
+                            .iter()
+                            .map(|e| e.id.clone())
+                            .collect();
                     }
                 }
-                "room" | "virtual_room" => {
-                    if section_rooms.entries.len() < limits.rooms_count {
-                        section_rooms.entries.push(super::ResultEntry {
-                            hit: hit.clone(),
-                            id: hit.room_code.to_string(),
-                            r#type: hit.r#type,
-                            name: formatted_name,
-                            subtext_bold: Some(hit.arch_name.unwrap_or_default()),
-                            ..super::ResultEntry::default()
-                        });
-
-                        // The first room in the results 'freezes' the number of visible buildings
-                        if section_buildings.n_visible == 0 && section_rooms.entries.len() == 1 {
-                            section_buildings.n_visible = section_buildings.entries.len();
-                        }
-                    }
-                }
-                _ => {}
-            };
-        }
+            }
+            _ => {}
+        };
     }
     section_rooms.n_visible = section_rooms.entries.len();
+    // collapsing joined buildings can have moved previously visible entries into children
+    section_buildings.n_visible =
+        count_visible_buildings(&section_buildings.entries, &visible_building_ids);
 
     (section_buildings, section_rooms)
 }
 
+/// Converts a single page of `results` of one facet into a section.
+///
+/// In contrast to [`merge_search_results`], nothing is deduplicated, reordered or collapsed.
+/// This keeps the pages consistent with each other and with `estimatedTotalHits`.
+#[tracing::instrument(skip(results))]
+pub(super) fn paginate_search_results(
+    page: &Page,
+    results: &SearchResults<MSHit>,
+) -> super::ResultsSection {
+    let entries = results
+        .hits
+        .iter()
+        .map(|hit| {
+            let formatted_name =
+                extract_formatted_name(hit).unwrap_or_else(|| hit.result.name.clone());
+            match page.facet {
+                PaginatedFacet::SitesBuildings => {
+                    building_entry(hit.result.clone(), formatted_name)
+                }
+                PaginatedFacet::Rooms => room_entry(hit.result.clone(), formatted_name),
+            }
+        })
+        .collect::<Vec<_>>();
+    let estimated_total_hits = results.estimated_total_hits.unwrap_or(0);
+    // meilisearch stops returning hits after maxTotalHits, even if more were estimated
+    let next_offset = page.offset + entries.len();
+    // an empty page would point to itself
+    let has_next_page =
+        !entries.is_empty() && entries.len() == page.limit && next_offset < estimated_total_hits;
+    super::ResultsSection {
+        facet: ResultFacet::from(page.facet),
+        n_visible: entries.len(),
+        entries,
+        estimated_total_hits,
+        next_offset: has_next_page.then_some(next_offset),
+    }
+}
+
+fn building_entry(hit: MSHit, formatted_name: String) -> super::ResultEntry {
+    super::ResultEntry {
+        hit: hit.clone(),
+        id: hit.room_code.to_string(),
+        r#type: hit.r#type,
+        name: formatted_name,
+        subtext: hit.type_common_name,
+        subtext_bold: None,
+        parsed_id: None,
+        children: Vec::new(),
+    }
+}
+
+fn room_entry(hit: MSHit, formatted_name: String) -> super::ResultEntry {
+    super::ResultEntry {
+        hit: hit.clone(),
+        id: hit.room_code.to_string(),
+        r#type: hit.r#type,
+        name: formatted_name,
+        subtext_bold: Some(hit.arch_name.unwrap_or_default()),
+        ..super::ResultEntry::default()
+    }
+}
+
+/// Adds the `entry` to the buildings section, collapsing joined buildings and their buildings.
+///
+/// - buildings of an already present `joined_building` are added as its children
+/// - a `joined_building` adopts its already present buildings as children
+fn collapse_into_buildings(
+    section_buildings: &mut super::ResultsSection,
+    mut entry: super::ResultEntry,
+    limits: &Limits,
+) {
+    let joined_parent = section_buildings
+        .entries
+        .iter_mut()
+        .find(|e| e.r#type == "joined_building" && entry.hit.is_located_in(&e.id));
+    if let Some(joined_parent) = joined_parent {
+        joined_parent.children.push(entry);
+        return;
+    }
+    if entry.r#type == "joined_building" {
+        let is_child =
+            |e: &super::ResultEntry| e.r#type == "building" && e.hit.is_located_in(&entry.id);
+        // the joined building takes the place of its first child
+        if let Some(position) = section_buildings.entries.iter().position(is_child) {
+            let (children, mut entries): (Vec<_>, Vec<_>) =
+                section_buildings.entries.drain(..).partition(is_child);
+            entry.children = children;
+            entries.insert(position, entry);
+            section_buildings.entries = entries;
+            return;
+        }
+    }
+    if section_buildings.entries.len() < limits.buildings_count {
+        section_buildings.entries.push(entry);
+    }
+}
+
+/// Number of `entries`, which are or contain one of the `visible_ids`.
+///
+/// Entries are only added at the end or in place of their children, so these are always the first entries.
+fn count_visible_buildings(entries: &[super::ResultEntry], visible_ids: &[String]) -> usize {
+    entries
+        .iter()
+        .filter(|e| {
+            visible_ids.contains(&e.id) || e.children.iter().any(|c| visible_ids.contains(&c.id))
+        })
+        .count()
+}
+
+/// Moves rooms located in one of the `buildings` in front of all other rooms.
+///
+/// Only the relative order of the rooms is changed, everything else keeps its position.
+/// This way, searching for `<building name> <room number>` ranks the rooms of said building first.
+fn prefer_rooms_in_buildings<'a>(
+    buildings: &[String],
+    hits: Vec<&'a SearchResult<MSHit>>,
+) -> Vec<&'a SearchResult<MSHit>> {
+    let is_room =
+        |hit: &SearchResult<MSHit>| matches!(hit.result.r#type.as_str(), "room" | "virtual_room");
+    let (mut rooms, mut other_rooms): (Vec<_>, Vec<_>) = hits
+        .iter()
+        .copied()
+        .filter(|hit| is_room(hit))
+        .partition(|hit| hit.result.is_located_in_any(buildings));
+    rooms.append(&mut other_rooms);
+    let mut rooms = rooms.into_iter();
+    hits.into_iter()
+        .map(|hit| match is_room(hit) {
+            true => rooms.next().expect("rooms were only reordered"),
+            false => hit,
+        })
+        .collect()
+}
+
 fn extract_formatted_name(hit: &SearchResult<MSHit>) -> Option<String> {
     Some(
         hit.formatted_result
@@ -105,3 +232,135 @@ fn extract_formatted_name(hit: &SearchResult<MSHit>) -> Option<String> {
             .to_string(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::search_executor::{ResultEntry, ResultsSection};
+
+    fn hit(room_code: &str, r#type: &str, parents: &[&str]) -> Value {
+        json!({
+            "ms_id": room_code.replace('.', "-"),
+            "room_code": room_code,
+            "name": room_code,
+            "type": r#type,
+            "type_common_name": r#type,
+            "parent_building_names": [],
+            "parent_keywords": parents,
+            "rank": 100,
+        })
+    }
+    fn results(hits: Vec<Value>) -> SearchResults<MSHit> {
+        serde_json::from_value(json!({
+            "estimatedTotalHits": hits.len(),
+            "hits": hits,
+            "processingTimeMs": 0,
+            "query": "",
+        }))
+        .unwrap()
+    }
+    fn merge(hits: Vec<Value>) -> (ResultsSection, ResultsSection) {
+        merge_search_results(
+            &Limits::default(),
+            &results(hits),
+            &results(vec![]),
+            &results(vec![]),
+        )
+    }
+    fn ids(entries: &[ResultEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn buildings_are_collapsed_into_their_joined_building() {
+        let (buildings, _) = merge(vec![
+            hit("5606", "building", &["garching", "mi"]),
+            hit("5510", "building", &["garching", "mw"]),
+            hit("mi", "joined_building", &["garching"]),
+            hit("5602", "building", &["garching", "mi"]),
+        ]);
+        assert_eq!(ids(&buildings.entries), vec!["mi", "5510"]);
+        assert_eq!(ids(&buildings.entries[0].children), vec!["5606", "5602"]);
+        assert!(buildings.entries[1].children.is_empty());
+    }
+
+    #[test]
+    fn children_keep_the_order_of_the_hits() {
+        let (buildings, _) = merge(vec![
+            hit("mi", "joined_building", &["garching"]),
+            hit("5602", "building", &["garching", "mi"]),
+            hit("5606", "building", &["garching", "mi"]),
+            hit("5601", "building", &["garching", "mi"]),
+        ]);
+        assert_eq!(ids(&buildings.entries), vec!["mi"]);
+        assert_eq!(
+            ids(&buildings.entries[0].children),
+            vec!["5602", "5606", "5601"]
+        );
+    }
+
+    #[test]
+    fn n_visible_counts_the_collapsed_buildings() {
+        let (buildings, rooms) = merge(vec![
+            hit("5606", "building", &["garching", "mi"]),
+            hit("5602", "building", &["garching", "mi"]),
+            hit("5606.EG.036", "room", &["garching", "mi", "5606"]),
+            hit("mi", "joined_building", &["garching"]),
+            hit("5510", "building", &["garching", "mw"]),
+        ]);
+        assert_eq!(ids(&rooms.entries), vec!["5606.EG.036"]);
+        assert_eq!(rooms.n_visible, 1);
+        // both visible buildings were collapsed into the joined building, which takes their place
+        assert_eq!(ids(&buildings.entries), vec!["mi", "5510"]);
+        assert_eq!(buildings.n_visible, 1);
+    }
+
+    #[test]
+    fn n_visible_without_collapsing() {
+        let (buildings, _) = merge(vec![
+            hit("5606", "building", &["garching", "mi"]),
+            hit("5606.EG.036", "room", &["garching", "mi", "5606"]),
+            hit("5510", "building", &["garching", "mw"]),
+        ]);
+        assert_eq!(ids(&buildings.entries), vec!["5606", "5510"]);
+        assert_eq!(buildings.n_visible, 1);
+    }
+
+    #[test]
+    fn pages_point_to_the_next_page() {
+        let page = |offset, limit| Page {
+            facet: PaginatedFacet::Rooms,
+            offset,
+            limit,
+        };
+        let mut rooms = results(vec![
+            hit("5606.EG.036", "room", &["garching", "mi", "5606"]),
+            hit("5606.EG.011", "room", &["garching", "mi", "5606"]),
+        ]);
+        rooms.estimated_total_hits = Some(5);
+        assert_eq!(
+            paginate_search_results(&page(0, 2), &rooms).next_offset,
+            Some(2)
+        );
+        // the last page
+        assert_eq!(
+            paginate_search_results(&page(3, 2), &rooms).next_offset,
+            None
+        );
+        // fewer hits than requested
+        assert_eq!(
+            paginate_search_results(&page(0, 3), &rooms).next_offset,
+            None
+        );
+        // an empty page never has a next page, even if more hits are estimated
+        let mut empty = results(vec![]);
+        empty.estimated_total_hits = Some(5);
+        assert_eq!(
+            paginate_search_results(&page(0, 0), &empty).next_offset,
+            None
+        );
+    }
+}
@@ -1,28 +1,59 @@
 use meilisearch_sdk::client::Client;
-use parser::TextToken;
+use meilisearch_sdk::search::SearchResults;
 use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Formatter};
 use tracing::error;
 
+use crate::db::location::AliasMatch;
 use crate::external::meilisearch::{GeoEntryQuery, MSHit};
 use crate::external::nominatim::Nominatim;
 use crate::limited::vec::LimitedVec;
-use crate::routes::search::{Highlighting, Limits};
-use crate::search_executor::parser::ParsedQuery;
+use crate::localisation::LangQueryArgs;
+use crate::routes::search::{Facets, Highlighting, Limits, Page, PaginatedFacet};
 
+#[expect(
+    unused_imports,
+    reason = "has to be imported as otherwise utoipa generates incorrect code"
+)]
+use serde_json::json;
+
+mod availability;
+mod explain;
+mod fallback;
 mod formatter;
 mod lexer;
 mod merger;
 mod parser;
+mod room_id;
+mod spelling;
+mod suggester;
+
+pub use availability::{UnavailableRooms, UnavailableRoomsKey};
+pub use explain::SearchExplanation;
+pub use parser::ParsedQuery;
+pub use suggester::Suggestion;
 
-#[derive(Serialize, Clone, Copy, utoipa::ToSchema)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ResultFacet {
+    /// The query is a room id, which resolved to exactly this entry
+    ExactMatch,
     SitesBuildings,
     Rooms,
     Addresses,
 }
 
+impl From<PaginatedFacet> for ResultFacet {
+    fn from(facet: PaginatedFacet) -> Self {
+        match facet {
+            PaginatedFacet::SitesBuildings => ResultFacet::SitesBuildings,
+            PaginatedFacet::Rooms => ResultFacet::Rooms,
+        }
+    }
+}
+
 #[derive(Serialize, Clone, utoipa::ToSchema)]
 pub struct ResultsSection {
     /// These indicate the type of item this represents
@@ -38,6 +69,30 @@ pub struct ResultsSection {
     #[serde(rename = "estimatedTotalHits")]
     #[schema(example = 6)]
     estimated_total_hits: usize,
+    /// The `offset` of the next page of this section
+    ///
+    /// Only present when paginating via `paginate` and if there are more entries to be fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 20)]
+    next_offset: Option<usize>,
+}
+
+impl ResultsSection {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Removes all entries, which are already shown in the `other` section
+    pub fn remove_entries_of(&mut self, other: &ResultsSection) {
+        let is_duplicate = |e: &ResultEntry| other.entries.iter().any(|o| o.id == e.id);
+        let removed_visible = self
+            .entries
+            .iter()
+            .take(self.n_visible)
+            .filter(|e| is_duplicate(e))
+            .count();
+        self.entries.retain(|e| !is_duplicate(e));
+        self.n_visible -= removed_visible;
+    }
 }
 
 impl Debug for ResultsSection {
@@ -55,6 +110,52 @@ impl Debug for ResultsSection {
     }
 }
 
+/// Number of entries matching the query per value of a facet
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq, utoipa::ToSchema)]
+pub struct FacetDistribution {
+    /// Counts per usage of the entries
+    ///
+    /// The values are slugified, same as the `usage:` filter expects them.
+    #[schema(example = json!({"büro": 120, "wc": 12}))]
+    usage: Option<BTreeMap<String, usize>>,
+    /// Counts per type of the entries
+    #[schema(example = json!({"room": 130, "building": 2}))]
+    r#type: Option<BTreeMap<String, usize>>,
+    /// Counts per campus of the entries
+    ///
+    /// The values are slugified, same as the `in:` filter expects them.
+    #[schema(example = json!({"garching": 120, "garching-hochbrück": 12}))]
+    campus: Option<BTreeMap<String, usize>>,
+    /// Counts per name of the buildings the entries are located in
+    #[schema(example = json!({"Maschinenwesen (MW)": 120, "Finger 06 (BT06)": 12}))]
+    parent_building_names: Option<BTreeMap<String, usize>>,
+}
+
+impl From<HashMap<String, HashMap<String, usize>>> for FacetDistribution {
+    fn from(mut distribution: HashMap<String, HashMap<String, usize>>) -> Self {
+        let mut take = |facet: &str| {
+            distribution
+                .remove(facet)
+                .map(|counts| counts.into_iter().collect())
+        };
+        Self {
+            usage: take("usage"),
+            r#type: take("type"),
+            campus: take("campus"),
+            parent_building_names: take("parent_building_names"),
+        }
+    }
+}
+
+/// Everything a search for geoentries returns
+#[derive(Debug, Clone)]
+pub struct GeoEntrySearchResults {
+    pub sections: LimitedVec<ResultsSection>,
+    /// Only present if any facets were requested
+    pub facets: Option<FacetDistribution>,
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Default, Debug, Clone, utoipa::ToSchema)]
 struct ResultEntry {
@@ -75,8 +176,8 @@ struct ResultEntry {
     /// Subtext to show below the search result.
     ///
     /// Usually contains the context of where this rooms is located in.
-    /// Currently not highlighted.
-    #[schema(example = "Maschinenwesen (MW)")]
+    /// Supports highlighting of the words matching the query or a parent filter (e.g. `@garching`).
+    #[schema(example = "Maschinenwesen (\x19MW\x17)")]
     subtext: String,
     /// Subtext to show below the search (by default in bold and after the non-bold subtext).
     ///
@@ -90,10 +191,17 @@ struct ResultEntry {
     /// It will be cropped to a maximum length to not take too much space in UIs.
     /// Supports highlighting.
     parsed_id: Option<String>,
+    /// Entries which are part of this entry and were collapsed into it.
+    ///
+    /// Currently only used to group the buildings of a `joined_building` below it.
+    /// Children don't count towards any of the limits.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(no_recursion)]
+    children: Vec<ResultEntry>,
 }
 
 #[tracing::instrument]
-pub async fn address_search(q: &str) -> LimitedVec<ResultsSection> {
+pub async fn address_search(q: &str, highlighting: Highlighting) -> LimitedVec<ResultsSection> {
     let results = match Nominatim::address_search(q).await {
         Ok(r) => r.0,
         Err(e) => {
@@ -102,12 +210,13 @@ pub async fn address_search(q: &str) -> LimitedVec<ResultsSection> {
         }
     };
     let num_results = results.len();
+    let highlighter = formatter::SubtextHighlighter::from((&ParsedQuery::from(q), highlighting));
     let section = ResultsSection {
         facet: ResultFacet::Addresses,
         entries: results
             .into_iter()
             .map(|r| {
-                let subtext = r.address.serialise();
+                let subtext = highlighter.highlight(&r.address.serialise());
                 ResultEntry {
                     hit: Default::default(),
                     id: format!("osm_{}", r.osm_id),
@@ -116,53 +225,117 @@ pub async fn address_search(q: &str) -> LimitedVec<ResultsSection> {
                     subtext,
                     subtext_bold: None,
                     parsed_id: None,
+                    children: Vec::new(),
                 }
             })
             .collect(),
         n_visible: num_results.min(15),
         estimated_total_hits: num_results,
+        next_offset: None,
     };
     LimitedVec::from(vec![section])
 }
 
-#[tracing::instrument(skip(client))]
+/// Searches for entries in meilisearch
+///
+/// `unavailable_rooms` (see [`unavailable_rooms`]) are excluded from the results.
+#[tracing::instrument(skip(client, unavailable_rooms))]
+#[expect(
+    clippy::too_many_arguments,
+    reason = "all of these are part of the cache key of the caller"
+)]
 pub async fn do_geoentry_search(
     client: &Client,
     q: &str,
     highlighting: Highlighting,
     limits: Limits,
-) -> LimitedVec<ResultsSection> {
+    facets: Facets,
+    page: Option<Page>,
+    lang: LangQueryArgs,
+    unavailable_rooms: &[String],
+) -> Result<GeoEntrySearchResults, meilisearch_sdk::errors::Error> {
     let parsed_input = ParsedQuery::from(q);
 
-    let query = parsed_input
-        .tokens
-        .clone()
-        .into_iter()
-        .map(|s| match s {
-            TextToken::Text(t) => t,
-            TextToken::SplittableText((t1, t2)) => format!("{t1} {t2} {t1}{t2}"),
-        })
-        .collect::<Vec<String>>()
-        .join(" ");
-    let mut query = GeoEntryQuery::from((client, query, &limits, &highlighting));
-    for sort in parsed_input.sorting.as_meilisearch_sorting() {
-        query.with_sorting(sort);
+    let mut query = geoentry_query(
+        client,
+        &parsed_input,
+        &limits,
+        &highlighting,
+        unavailable_rooms,
+    );
+    for facet in facets.as_meilisearch_facets() {
+        query.with_facet_distribution(facet);
     }
-    if !parsed_input.filters.is_empty() {
-        query.with_filtering(parsed_input.filters.as_meilisearch_filters());
+    if let Some(page) = page {
+        return do_paginated_search(query, parsed_input, highlighting, facets, page, lang).await;
     }
 
-    let Ok(response) = query.execute().await else {
-        // error should be serde_json::error
-        error!("Error searching for results");
-        return LimitedVec(vec![]);
-    };
-    let (section_buildings, mut section_rooms) = merger::merge_search_results(
+    let mut response = query.execute().await?;
+    if lang.should_use_english() {
+        response.results.iter_mut().for_each(localise_to_english);
+    }
+    let merged = response.results.first().unwrap();
+    let facet_distribution = (!facets.is_empty())
+        .then(|| FacetDistribution::from(merged.facet_distribution.clone().unwrap_or_default()));
+    let sections = merge_sections(
         &limits,
-        response.results.first().unwrap(),
+        parsed_input,
+        highlighting,
+        merged,
         response.results.get(1).unwrap(),
         response.results.get(2).unwrap(),
     );
+    Ok(GeoEntrySearchResults {
+        sections,
+        facets: facet_distribution,
+    })
+}
+
+/// The query for meilisearch, which includes the sorting and filters of the `parsed_input`
+fn geoentry_query(
+    client: &Client,
+    parsed_input: &ParsedQuery,
+    limits: &Limits,
+    highlighting: &Highlighting,
+    unavailable_rooms: &[String],
+) -> GeoEntryQuery {
+    let query = parsed_input.as_meilisearch_query();
+    let mut query = GeoEntryQuery::from((client, query, limits, highlighting));
+    for sort in parsed_input.sorting.as_meilisearch_sorting() {
+        query.with_sorting(sort);
+    }
+    if !parsed_input.filters.is_empty() {
+        query.with_filtering(parsed_input.filters.as_meilisearch_filters());
+    }
+    if !unavailable_rooms.is_empty() {
+        let room_codes = unavailable_rooms
+            .iter()
+            .map(|room_code| as_filter_string(room_code))
+            .collect::<Vec<String>>()
+            .join(", ");
+        query.with_filtering(format!("(NOT room_code IN [{room_codes}])"));
+    }
+    query
+}
+
+/// Quotes `value` as a string of a meilisearch filter
+///
+/// Inside quotes, meilisearch only treats `\` and the quote itself as special.
+fn as_filter_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Merges the results of the three sub-queries of a [`GeoEntryQuery`] into the buildings and rooms sections
+fn merge_sections(
+    limits: &Limits,
+    parsed_input: ParsedQuery,
+    highlighting: Highlighting,
+    merged_results: &SearchResults<MSHit>,
+    buildings_results: &SearchResults<MSHit>,
+    rooms_results: &SearchResults<MSHit>,
+) -> LimitedVec<ResultsSection> {
+    let (section_buildings, mut section_rooms) =
+        merger::merge_search_results(limits, merged_results, buildings_results, rooms_results);
     let visitor = formatter::RoomVisitor::from((parsed_input, highlighting));
     section_rooms
         .entries
@@ -175,6 +348,267 @@ pub async fn do_geoentry_search(
     }
 }
 
+/// The entry a query of a batch most likely refers to
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct BatchMatch {
+    /// The query, as it was requested
+    #[schema(example = "MW 1801")]
+    query: String,
+    /// The entry the query most likely refers to
+    ///
+    /// Not present if nothing matched the query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_match: Option<ResultEntry>,
+    /// How confident we are that `best_match` is what the query refers to
+    ///
+    /// Ranges from `0.0` (nothing matched) to `1.0`.
+    /// `1.0` is also used if the query is a room id, which resolved to exactly this entry.
+    /// Otherwise, this is the ranking score of the search, meaning that it is only comparable between queries and not an exact probability.
+    #[schema(example = 0.93, minimum = 0.0, maximum = 1.0)]
+    confidence: f64,
+}
+
+impl BatchMatch {
+    /// Prefers the entry of the `exact_match` section (see [`exact_match`]) over the best match of the search
+    pub fn with_exact_match(self, exact_match: Option<ResultsSection>) -> Self {
+        let Some(entry) = exact_match.and_then(|s| s.entries.into_iter().next()) else {
+            return self;
+        };
+        Self {
+            best_match: Some(entry),
+            confidence: 1.0,
+            ..self
+        }
+    }
+}
+
+/// Searches the best match for each of the `queries`, sharing a single round trip to meilisearch
+///
+/// The matches are returned in the same order as the `queries`.
+#[tracing::instrument(skip(client))]
+pub async fn do_batch_search(
+    client: &Client,
+    queries: &[String],
+    highlighting: Highlighting,
+    limits: Limits,
+    lang: LangQueryArgs,
+) -> Result<Vec<BatchMatch>, meilisearch_sdk::errors::Error> {
+    let parsed_inputs = queries
+        .iter()
+        .map(|q| ParsedQuery::from(q.as_str()))
+        .collect::<Vec<ParsedQuery>>();
+    let geoentry_queries = parsed_inputs
+        .iter()
+        .map(|parsed_input| geoentry_query(client, parsed_input, &limits, &highlighting, &[]))
+        .collect::<Vec<GeoEntryQuery>>();
+    let mut response = GeoEntryQuery::execute_batch(client, &geoentry_queries).await?;
+    if lang.should_use_english() {
+        response.results.iter_mut().for_each(localise_to_english);
+    }
+
+    let matches = queries
+        .iter()
+        .zip(parsed_inputs)
+        .zip(response.results.chunks_exact(3))
+        .map(|((q, parsed_input), results)| {
+            let sections = merge_sections(
+                &limits,
+                parsed_input,
+                highlighting.clone(),
+                &results[0],
+                &results[1],
+                &results[2],
+            );
+            let best_match = sections
+                .0
+                .into_iter()
+                .find_map(|section| section.entries.into_iter().next());
+            // the same entry can be hit by several sub-queries => the best score counts
+            let confidence = best_match.as_ref().map_or(0.0, |entry| {
+                results
+                    .iter()
+                    .flat_map(|r| r.hits.iter())
+                    .filter(|hit| hit.result.room_code == entry.id)
+                    .filter_map(|hit| hit.ranking_score)
+                    .fold(0.0, f64::max)
+            });
+            BatchMatch {
+                query: q.clone(),
+                best_match,
+                confidence,
+            }
+        })
+        .collect();
+    Ok(matches)
+}
+
+/// Searches only a single page of one facet
+async fn do_paginated_search(
+    query: GeoEntryQuery,
+    parsed_input: ParsedQuery,
+    highlighting: Highlighting,
+    facets: Facets,
+    page: Page,
+    lang: LangQueryArgs,
+) -> Result<GeoEntrySearchResults, meilisearch_sdk::errors::Error> {
+    let mut response = query
+        .execute_page(page.facet, page.offset, page.limit)
+        .await?;
+    if lang.should_use_english() {
+        localise_to_english(&mut response);
+    }
+    let facet_distribution = (!facets.is_empty())
+        .then(|| FacetDistribution::from(response.facet_distribution.clone().unwrap_or_default()));
+    let mut section = merger::paginate_search_results(&page, &response);
+    if page.facet == PaginatedFacet::Rooms {
+        let visitor = formatter::RoomVisitor::from((parsed_input, highlighting));
+        section.entries.iter_mut().for_each(|r| visitor.visit(r));
+    }
+    Ok(GeoEntrySearchResults {
+        sections: LimitedVec(vec![section]),
+        facets: facet_distribution,
+    })
+}
+
+/// Searches entries in postgres, for when meilisearch is unavailable
+///
+/// See [`fallback::search`] for the (many) limitations.
+#[tracing::instrument(skip(pool))]
+pub async fn do_fallback_search(
+    pool: &PgPool,
+    q: &str,
+    limits: Limits,
+    lang: LangQueryArgs,
+) -> LimitedVec<ResultsSection> {
+    let parsed_input = ParsedQuery::from(q);
+    match fallback::search(pool, &parsed_input, limits, lang.should_use_english()).await {
+        Ok(sections) => sections,
+        Err(e) => {
+            error!(error = ?e, "Error searching for results in the database");
+            LimitedVec(vec![])
+        }
+    }
+}
+
+/// Replaces the german names of all hits (including the highlighted ones) by their english translations
+fn localise_to_english(results: &mut SearchResults<MSHit>) {
+    for hit in results.hits.iter_mut() {
+        hit.result.localise_to_english();
+        if let Some(formatted) = hit.formatted_result.as_mut() {
+            if let Some(name @ serde_json::Value::String(_)) = formatted.remove("name_en") {
+                formatted.insert("name".to_string(), name);
+            }
+        }
+    }
+}
+
+/// Resolves the query to a single entry, if it is a room id in one of the commonly used formats
+///
+/// See [`room_id::RoomId`] for the supported formats.
+#[tracing::instrument(skip(pool))]
+pub async fn exact_match(pool: &PgPool, q: &str, lang: LangQueryArgs) -> Option<ResultsSection> {
+    let room_id = room_id::RoomId::parse(q)?;
+    let resolved = match &room_id {
+        room_id::RoomId::Tumonline(id) | room_id::RoomId::ArchName(id) => {
+            AliasMatch::fetch_by_alias(pool, id, lang.should_use_english()).await
+        }
+        room_id::RoomId::PrefixedArchId { building, arch_id } => {
+            let should_use_english = lang.should_use_english();
+            AliasMatch::fetch_by_building_arch_id(pool, building, arch_id, should_use_english).await
+        }
+    };
+    let resolved = match resolved {
+        Ok(resolved) => resolved?,
+        Err(e) => {
+            error!(error = ?e, ?room_id, "Error resolving the room id");
+            return None;
+        }
+    };
+    Some(ResultsSection {
+        facet: ResultFacet::ExactMatch,
+        entries: vec![ResultEntry {
+            id: resolved.key,
+            r#type: resolved.r#type,
+            name: resolved.name,
+            subtext: resolved.type_common_name,
+            parsed_id: Some(room_id.to_string()),
+            ..ResultEntry::default()
+        }],
+        n_visible: 1,
+        estimated_total_hits: 1,
+        next_offset: None,
+    })
+}
+
+/// Rooms, which are not free during the `free:` windows of the query (e.g. `free:now` or `free:14:00-16:00`)
+///
+/// Empty if the query has no `free:` filter.
+/// Rooms without any calendar are not included, as they are already excluded by the filter itself.
+///
+/// `None` if the rooms could not be looked up, in which case the `free:` filter cannot be applied.
+#[tracing::instrument(skip(pool))]
+pub async fn unavailable_rooms(pool: &PgPool, q: &str) -> Option<UnavailableRooms> {
+    let parsed_input = ParsedQuery::from(q);
+    if parsed_input.filters.free_windows().is_empty() {
+        return Some(UnavailableRooms::default());
+    }
+    match availability::unavailable_rooms(pool, &parsed_input, chrono::Utc::now()).await {
+        Ok(rooms) => Some(rooms),
+        Err(e) => {
+            error!(error = ?e, "Error looking up which rooms are occupied");
+            None
+        }
+    }
+}
+
+/// Proposes a corrected query, if `q` likely contains a misspelled word
+///
+/// Intended for queries without any results, as Meilisearch does not tolerate more than two typos per word.
+#[tracing::instrument(skip(pool))]
+pub async fn did_you_mean(pool: &PgPool, q: &str) -> Option<String> {
+    match spelling::cached_vocabulary(pool).await {
+        Ok(vocabulary) => vocabulary.correct(q),
+        Err(e) => {
+            error!(error = ?e, "Error loading the vocabulary for spelling corrections");
+            None
+        }
+    }
+}
+
+/// Explains how the query is interpreted and ranked, for debugging unexpected results
+///
+/// This executes the search again, so should not be used for regular searches.
+#[tracing::instrument(skip(client, unavailable_rooms))]
+pub async fn explain(
+    client: &Client,
+    q: &str,
+    highlighting: Highlighting,
+    limits: Limits,
+    unavailable_rooms: &[String],
+) -> Option<SearchExplanation> {
+    match explain::explain(client, q, highlighting, limits, unavailable_rooms).await {
+        Ok(explanation) => Some(explanation),
+        Err(e) => {
+            error!(error = ?e, "Error explaining the search");
+            None
+        }
+    }
+}
+
+/// Completes the last (possibly partial) token of the query
+///
+/// In contrast to [`do_geoentry_search`] this does only a single, lightweight query.
+#[tracing::instrument(skip(client))]
+pub async fn do_suggest(client: &Client, q: &str, limit: usize) -> Vec<Suggestion> {
+    match suggester::suggest(client, q, limit).await {
+        Ok(suggestions) => suggestions,
+        Err(e) => {
+            error!(error = ?e, "Error suggesting completions");
+            vec![]
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::fmt::{Display, Formatter};
@@ -208,8 +642,14 @@ mod test {
                 &self.query,
                 Highlighting::default(),
                 Limits::default(),
+                Facets::default(),
+                None,
+                LangQueryArgs::default(),
+                &[],
             )
             .await
+            .unwrap()
+            .sections
             .0
         }
     }
@@ -228,11 +668,93 @@ mod test {
             Ok(())
         }
     }
+    #[test]
+    fn filter_strings_are_escaped() {
+        assert_eq!(as_filter_string("5602.EG.001"), r#""5602.EG.001""#);
+        assert_eq!(as_filter_string(r#"a"] OR [""#), r#""a\"] OR [\"""#);
+        assert_eq!(as_filter_string(r"a\"), r#""a\\""#);
+    }
+
+    #[test]
+    fn english_results_are_localised() {
+        let building = serde_json::json!({
+            "ms_id": "5602",
+            "room_code": "5602",
+            "name": "Finger 02 (BT02)",
+            "name_en": "Wing 02 (BT02)",
+            "type": "building",
+            "type_common_name": "Gebäudeteil",
+            "type_common_name_en": "Building part",
+            "parent_building_names": [],
+            "parent_keywords": ["garching", "mi"],
+            "rank": 100,
+        });
+        let room = serde_json::json!({
+            "ms_id": "5602-EG-001",
+            "room_code": "5602.EG.001",
+            "name": "5602.EG.001 (MI HS 1, Hörsaal)",
+            "name_en": "5602.EG.001 (MI HS 1, Lecture hall)",
+            "arch_name": "00.02.001@5602",
+            "type": "room",
+            "type_common_name": "Hörsaal",
+            "type_common_name_en": "Lecture hall",
+            "parent_building_names": ["Informatik"],
+            "parent_building_names_en": ["Computer Science"],
+            "parent_keywords": ["garching", "mi", "5602"],
+            "campus": "Garching",
+            "rank": 100,
+            "_formatted": {
+                "name": "5602.EG.001 (\u{0019}MI\u{0017} HS 1, Hörsaal)",
+                "name_en": "5602.EG.001 (\u{0019}MI\u{0017} HS 1, Lecture hall)",
+            },
+        });
+        let results = |hits: Vec<serde_json::Value>| -> SearchResults<MSHit> {
+            serde_json::from_value(serde_json::json!({
+                "estimatedTotalHits": hits.len(),
+                "hits": hits,
+                "processingTimeMs": 0,
+                "query": "mi",
+            }))
+            .unwrap()
+        };
+        let mut merged = results(vec![building, room]);
+        localise_to_english(&mut merged);
+        let sections = merge_sections(
+            &Limits::default(),
+            ParsedQuery::from("mi"),
+            Highlighting::default(),
+            &merged,
+            &results(vec![]),
+            &results(vec![]),
+        );
+        let entries = sections
+            .0
+            .into_iter()
+            .flat_map(|s| s.entries)
+            .map(|e| (e.id, e.name, e.subtext))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "5602".to_string(),
+                    "Wing 02 (BT02)".to_string(),
+                    "Building part".to_string()
+                ),
+                (
+                    "5602.EG.001".to_string(),
+                    "5602.EG.001 (\u{0019}MI\u{0017} HS 1, Lecture hall)".to_string(),
+                    "Garching, Computer Science".to_string()
+                ),
+            ]
+        );
+    }
+
     #[tokio::test]
     #[tracing_test::traced_test]
     async fn test_good_queries() {
         let ms = MeiliSearchTestContainer::new().await;
-        crate::setup::meilisearch::load_data(&ms.client)
+        crate::setup::meilisearch::reindex(&ms.client)
             .await
             .unwrap();
         for query in TestQuery::load_good() {
@@ -253,11 +775,51 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_batch_matches_single_searches() {
+        let ms = MeiliSearchTestContainer::new().await;
+        crate::setup::meilisearch::reindex(&ms.client)
+            .await
+            .unwrap();
+        let queries = TestQuery::load_good();
+        let qs = queries
+            .iter()
+            .map(|query| query.query.clone())
+            .collect::<Vec<String>>();
+        let matches = do_batch_search(
+            &ms.client,
+            &qs,
+            Highlighting::default(),
+            Limits::default(),
+            LangQueryArgs::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(matches.len(), queries.len());
+        for (query, batch_match) in queries.iter().zip(matches) {
+            let actual = query.search(&ms.client).await;
+            let expected = actual
+                .into_iter()
+                .find_map(|s| s.entries.into_iter().next());
+            assert_eq!(
+                batch_match.best_match.as_ref().map(|e| &e.id),
+                expected.as_ref().map(|e| &e.id),
+                "{query}"
+            );
+            assert!((0.0..=1.0).contains(&batch_match.confidence), "{query}");
+            assert_eq!(
+                batch_match.best_match.is_some(),
+                batch_match.confidence > 0.0
+            );
+        }
+    }
+
     #[tokio::test]
     #[tracing_test::traced_test]
     async fn test_bad_queries() {
         let ms = MeiliSearchTestContainer::new().await;
-        crate::setup::meilisearch::load_data(&ms.client)
+        crate::setup::meilisearch::reindex(&ms.client)
             .await
             .unwrap();
         for query in TestQuery::load_bad() {
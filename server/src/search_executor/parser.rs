@@ -1,15 +1,30 @@
 use logos::Logos;
+use serde::Serialize;
 use std::collections::HashSet;
 use std::fmt::{Debug, Formatter};
 use tracing::warn;
 
 use super::lexer::Token;
 
-#[derive(Clone, Default, PartialEq, Eq)]
+#[derive(Clone, Default, PartialEq, Eq, Serialize)]
 pub struct Filter {
     parents: HashSet<String>,
     types: HashSet<String>,
     usages: HashSet<String>,
+    floors: HashSet<String>,
+    /// slugified operator codes (e.g. `tus7000`) or ids (e.g. `51901`)
+    operators: HashSet<String>,
+    /// `now` or `HH:MM-HH:MM` (today), in which rooms have to be free
+    free_windows: HashSet<String>,
+    /// `free:` filters, whose window is not valid (e.g. `free:25:00-26:00`)
+    malformed_free_windows: HashSet<String>,
+    /// `lat,lon,radius_m`
+    geo_radii: HashSet<String>,
+    /// `(top_right, bottom_left)`, each as `lat,lon`
+    geo_bounding_boxes: HashSet<(String, String)>,
+    excluded_parents: HashSet<String>,
+    excluded_types: HashSet<String>,
+    excluded_usages: HashSet<String>,
 }
 impl Filter {
     pub fn as_meilisearch_filters(&self) -> String {
@@ -28,10 +43,72 @@ impl Filter {
             let usages: Vec<&str> = self.usages.iter().map(String::as_str).collect();
             filters.push(format!("(usage IN {usages:?})"));
         }
+        if !self.floors.is_empty() {
+            let floors: Vec<&str> = self.floors.iter().map(String::as_str).collect();
+            filters.push(format!("(floor IN {floors:?})"));
+        }
+        if !self.operators.is_empty() {
+            let operators: Vec<&str> = self.operators.iter().map(String::as_str).collect();
+            filters.push(format!(
+                "((operator_code IN {operators:?}) OR (operator_id IN {operators:?}))"
+            ));
+        }
+        // which rooms are occupied is not known to meilisearch => only rooms with a calendar can be filtered here
+        if !self.free_windows.is_empty() {
+            filters.push("(has_calendar = true)".to_string());
+        }
+        for radius in &self.geo_radii {
+            filters.push(format!("_geoRadius({radius})"));
+        }
+        for (top_right, bottom_left) in &self.geo_bounding_boxes {
+            filters.push(format!("_geoBoundingBox([{top_right}], [{bottom_left}])"));
+        }
+        if !self.excluded_parents.is_empty() {
+            let parents: Vec<&str> = self.excluded_parents.iter().map(String::as_str).collect();
+            filters.push(format!(
+                "((NOT parent_keywords IN {parents:?}) AND (NOT parent_building_names IN {parents:?}) AND (NOT campus IN {parents:?}))"
+            ));
+        }
+        if !self.excluded_types.is_empty() {
+            let types: Vec<&str> = self.excluded_types.iter().map(String::as_str).collect();
+            filters.push(format!("(NOT type IN {types:?})"));
+        }
+        if !self.excluded_usages.is_empty() {
+            let usages: Vec<&str> = self.excluded_usages.iter().map(String::as_str).collect();
+            filters.push(format!("(NOT usage IN {usages:?})"));
+        }
         filters.join(" AND ")
     }
+    /// The (slugified) parents of the `in:` filters
+    pub fn parents(&self) -> &HashSet<String> {
+        &self.parents
+    }
+    /// The (slugified) usages of the `usage:` filters
+    pub fn usages(&self) -> &HashSet<String> {
+        &self.usages
+    }
+    /// The windows of the `free:` filters, in which rooms have to be free
+    pub fn free_windows(&self) -> &HashSet<String> {
+        &self.free_windows
+    }
+    /// The `free:` filters, which could not be understood
+    ///
+    /// As these are not applied, the results would include rooms which are not free.
+    pub fn malformed_free_windows(&self) -> &HashSet<String> {
+        &self.malformed_free_windows
+    }
     pub fn is_empty(&self) -> bool {
-        self.parents.is_empty() && self.types.is_empty() && self.usages.is_empty()
+        self.parents.is_empty()
+            && self.types.is_empty()
+            && self.usages.is_empty()
+            && self.floors.is_empty()
+            && self.operators.is_empty()
+            && self.free_windows.is_empty()
+            && self.geo_radii.is_empty()
+            && self.geo_bounding_boxes.is_empty()
+            && self.excluded_parents.is_empty()
+            && self.excluded_types.is_empty()
+            && self.excluded_usages.is_empty()
     }
 }
 
@@ -42,16 +119,43 @@ impl Debug for Filter {
             base.field("parents", &self.parents);
         }
         if !self.types.is_empty() {
-            base.field("types", &self.parents);
+            base.field("types", &self.types);
         }
         if !self.usages.is_empty() {
-            base.field("usages", &self.parents);
+            base.field("usages", &self.usages);
+        }
+        if !self.floors.is_empty() {
+            base.field("floors", &self.floors);
+        }
+        if !self.operators.is_empty() {
+            base.field("operators", &self.operators);
+        }
+        if !self.free_windows.is_empty() {
+            base.field("free_windows", &self.free_windows);
+        }
+        if !self.malformed_free_windows.is_empty() {
+            base.field("malformed_free_windows", &self.malformed_free_windows);
+        }
+        if !self.geo_radii.is_empty() {
+            base.field("geo_radii", &self.geo_radii);
+        }
+        if !self.geo_bounding_boxes.is_empty() {
+            base.field("geo_bounding_boxes", &self.geo_bounding_boxes);
+        }
+        if !self.excluded_parents.is_empty() {
+            base.field("excluded_parents", &self.excluded_parents);
+        }
+        if !self.excluded_types.is_empty() {
+            base.field("excluded_types", &self.excluded_types);
+        }
+        if !self.excluded_usages.is_empty() {
+            base.field("excluded_usages", &self.excluded_usages);
         }
         base.finish()
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub struct Sorting {
     location: HashSet<String>,
 }
@@ -68,13 +172,14 @@ impl Sorting {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TextToken {
     Text(String),
     SplittableText((String, String)),
 }
 
-#[derive(Clone, Default, PartialEq, Eq)]
+#[derive(Clone, Default, PartialEq, Eq, Serialize)]
 pub struct ParsedQuery {
     pub tokens: Vec<TextToken>,
     pub filters: Filter,
@@ -96,6 +201,19 @@ impl Debug for ParsedQuery {
 }
 
 impl ParsedQuery {
+    /// The text tokens joined into a query string for meilisearch
+    ///
+    /// Splittable texts are included both split and unsplit, as both variants can be relevant.
+    pub fn as_meilisearch_query(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|s| match s {
+                TextToken::Text(t) => t.clone(),
+                TextToken::SplittableText((t1, t2)) => format!("{t1} {t2} {t1}{t2}"),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
     pub fn relevant_enough_for_room_highligting(&self) -> bool {
         if self.tokens.len() == 1 {
             return true;
@@ -113,7 +231,8 @@ impl ParsedQuery {
 impl From<&str> for ParsedQuery {
     fn from(query: &str) -> Self {
         let mut result = Self::default();
-        for token in Token::lexer(query) {
+        let mut lexer = Token::lexer(query);
+        while let Some(token) = lexer.next() {
             match token {
                 Ok(Token::Text(s)) => {
                     result.tokens.push(TextToken::Text(s));
@@ -130,11 +249,43 @@ impl From<&str> for ParsedQuery {
                 Ok(Token::TypeFilter(filter)) => {
                     result.filters.types.insert(filter);
                 }
+                Ok(Token::FloorFilter(filter)) => {
+                    result.filters.floors.insert(filter);
+                }
+                Ok(Token::OperatorFilter(filter)) => {
+                    result.filters.operators.insert(filter);
+                }
+                Ok(Token::FreeFilter(window)) => {
+                    result.filters.free_windows.insert(window);
+                }
+                Ok(Token::GeoRadiusFilter(filter)) => {
+                    result.filters.geo_radii.insert(filter);
+                }
+                Ok(Token::GeoBoundingBoxFilter(filter)) => {
+                    result.filters.geo_bounding_boxes.insert(filter);
+                }
+                Ok(Token::NegatedParentFilter(filter)) => {
+                    result.filters.excluded_parents.insert(filter);
+                }
+                Ok(Token::NegatedUsageFilter(filter)) => {
+                    result.filters.excluded_usages.insert(filter);
+                }
+                Ok(Token::NegatedTypeFilter(filter)) => {
+                    result.filters.excluded_types.insert(filter);
+                }
                 Ok(Token::LocationSort(location)) => {
                     result.sorting.location.insert(location);
                 }
                 Err(_) => {
-                    warn!("Error in query parsing");
+                    let slice = lexer.slice();
+                    let lowercase = slice.to_lowercase();
+                    if lowercase.starts_with("free:") || lowercase.starts_with("frei:") {
+                        result
+                            .filters
+                            .malformed_free_windows
+                            .insert(slice.to_string());
+                    }
+                    warn!(slice, "Error in query parsing");
                 }
             }
         }
@@ -191,6 +342,159 @@ mod tests {
         }
     }
 
+    #[test]
+    fn negated_parent_filter() {
+        for filter in ["-in:", "!in:", "not:in:", "-@", "!@", "not:@"] {
+            for sep in ["", " "] {
+                assert_eq!(
+                    ParsedQuery::from(format!("{filter}{sep}foo").as_str()).filters,
+                    Filter {
+                        excluded_parents: HashSet::from(["foo".to_string()]),
+                        ..Default::default()
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn negated_usage_filters() {
+        for filter in ["-usage:", "!nutzung:", "not:usage:", "!="] {
+            for sep in ["", " "] {
+                assert_eq!(
+                    ParsedQuery::from(format!("{filter}{sep}foo").as_str()).filters,
+                    Filter {
+                        excluded_usages: HashSet::from(["foo".to_string()]),
+                        ..Default::default()
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn negated_type_filters() {
+        for filter in ["-type:", "!type:", "not:type:"] {
+            for sep in ["", " "] {
+                assert_eq!(
+                    ParsedQuery::from(format!("{filter}{sep}foo").as_str()).filters,
+                    Filter {
+                        excluded_types: HashSet::from(["foo".to_string()]),
+                        ..Default::default()
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_positive_and_negated_filters() {
+        let filters = ParsedQuery::from("wc in:garching -in:5606 !type:virtual_room").filters;
+        assert_eq!(
+            filters,
+            Filter {
+                parents: HashSet::from(["garching".to_string()]),
+                excluded_parents: HashSet::from(["5606".to_string()]),
+                excluded_types: HashSet::from(["virtual_room".to_string()]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            filters.as_meilisearch_filters(),
+            r#"((parent_keywords IN ["garching"]) OR (parent_building_names IN ["garching"]) OR (campus IN ["garching"])) AND ((NOT parent_keywords IN ["5606"]) AND (NOT parent_building_names IN ["5606"]) AND (NOT campus IN ["5606"])) AND (NOT type IN ["virtual_room"])"#
+        );
+    }
+
+    #[test]
+    fn floor_filter() {
+        let parsed = ParsedQuery::from("usage:wc in:mi floor:1");
+        assert_eq!(
+            parsed.filters,
+            Filter {
+                parents: HashSet::from(["mi".to_string()]),
+                usages: HashSet::from(["wc".to_string()]),
+                floors: HashSet::from(["01".to_string()]),
+                ..Default::default()
+            }
+        );
+        assert!(parsed.tokens.is_empty());
+        assert_eq!(
+            ParsedQuery::from("floor:U1")
+                .filters
+                .as_meilisearch_filters(),
+            r#"(floor IN ["U1"])"#
+        );
+    }
+
+    #[test]
+    fn operator_filter() {
+        let parsed = ParsedQuery::from("hörsaal org:TUS7000 operator:51901");
+        assert_eq!(
+            parsed.filters,
+            Filter {
+                operators: HashSet::from(["tus7000".to_string(), "51901".to_string()]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            ParsedQuery::from("operator:TUS7000")
+                .filters
+                .as_meilisearch_filters(),
+            r#"((operator_code IN ["tus7000"]) OR (operator_id IN ["tus7000"]))"#
+        );
+    }
+
+    #[test]
+    fn free_filter() {
+        let parsed = ParsedQuery::from("seminarraum free:now in:garching");
+        assert_eq!(
+            parsed.filters,
+            Filter {
+                parents: HashSet::from(["garching".to_string()]),
+                free_windows: HashSet::from(["now".to_string()]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            ParsedQuery::from("free:14:00-16:00")
+                .filters
+                .as_meilisearch_filters(),
+            "(has_calendar = true)"
+        );
+    }
+
+    #[test]
+    fn malformed_free_filter() {
+        let parsed = ParsedQuery::from("hörsaal free:25:00-26:00 frei:16:00-14:00");
+        assert_eq!(
+            parsed.filters,
+            Filter {
+                malformed_free_windows: HashSet::from([
+                    "free:25:00-26:00".to_string(),
+                    "frei:16:00-14:00".to_string()
+                ]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(parsed.tokens, vec![TextToken::Text("hörsaal".to_string())]);
+    }
+
+    #[test]
+    fn geo_filters() {
+        let parsed = ParsedQuery::from("hörsaal within:48.262,11.668,500");
+        assert_eq!(parsed.tokens, vec![TextToken::Text("hörsaal".to_string())]);
+        assert_eq!(
+            parsed.filters.as_meilisearch_filters(),
+            "_geoRadius(48.262,11.668,500)"
+        );
+        let parsed = ParsedQuery::from("type:room bbox:48.26,11.66,48.27,11.68");
+        assert_eq!(
+            parsed.filters.as_meilisearch_filters(),
+            r#"(type IN ["room"]) AND _geoBoundingBox([48.27,11.68], [48.26,11.66])"#
+        );
+        assert!(parsed.sorting.is_empty());
+    }
+
     #[test]
     fn location_sort() {
         for sep in ["", " "] {
@@ -245,7 +549,7 @@ mod tests {
         );
         assert_eq!(
             ParsedQuery::from(
-                "foo in:abc bar @abc =def usage:dd nutzung:gh type:fdh foo near:45.32,59.3"
+                "foo in:abc bar @abc =def usage:dd nutzung:gh type:fdh -in:x !type:y foo near:45.32,59.3"
             )
             .tokens,
             vec![
@@ -0,0 +1,142 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+static TUMONLINE_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<building>[0-9]{4})\.(?<floor>[0-9a-zA-Z]{2})\.(?<room>[0-9a-zA-Z]+)$").unwrap()
+});
+static ARCH_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<arch_id>[0-9a-zA-Z][0-9a-zA-Z.]*) ?@ ?(?<building>[0-9]{4})$").unwrap()
+});
+// the architects id has at least three digits or a floor part, as otherwise numbered names like `HS1` or `WC2` would match
+static PREFIXED_ARCH_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?<building>[a-zA-Z]{2,4}) ?(?<arch_id>[0-9]{3}[0-9a-zA-Z.]*|[0-9]+\.[0-9a-zA-Z.]+)$",
+    )
+    .unwrap()
+});
+
+/// A room id in one of the formats users commonly paste
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomId {
+    /// The TUMonline id, e.g. `5606.EG.036`
+    ///
+    /// This is also the key of the room.
+    Tumonline(String),
+    /// The architects name, e.g. `3002@5510`
+    ArchName(String),
+    /// The architects id prefixed by the short name of a building, e.g. `MI 00.08.038` or `MW2001`
+    PrefixedArchId { building: String, arch_id: String },
+}
+
+impl RoomId {
+    /// Recognises the query as a room id, if the whole query is one
+    pub fn parse(q: &str) -> Option<Self> {
+        let q = q.trim();
+        if let Some(captures) = TUMONLINE_ID.captures(q) {
+            return Some(Self::Tumonline(format!(
+                "{}.{}.{}",
+                &captures["building"],
+                captures["floor"].to_uppercase(),
+                captures["room"].to_uppercase(),
+            )));
+        }
+        if let Some(captures) = ARCH_NAME.captures(q) {
+            return Some(Self::ArchName(format!(
+                "{}@{}",
+                &captures["arch_id"], &captures["building"],
+            )));
+        }
+        if let Some(captures) = PREFIXED_ARCH_ID.captures(q) {
+            return Some(Self::PrefixedArchId {
+                building: captures["building"].to_lowercase(),
+                arch_id: captures["arch_id"].to_string(),
+            });
+        }
+        None
+    }
+}
+
+impl std::fmt::Display for RoomId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomId::Tumonline(id) | RoomId::ArchName(id) => f.write_str(id),
+            RoomId::PrefixedArchId { building, arch_id } => {
+                write!(f, "{} {arch_id}", building.to_uppercase())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn tumonline_ids() {
+        for q in ["5606.EG.036", "5606.eg.036", " 5606.EG.036 "] {
+            assert_eq!(
+                RoomId::parse(q),
+                Some(RoomId::Tumonline("5606.EG.036".to_string())),
+                "{q}"
+            );
+        }
+        assert_eq!(
+            RoomId::parse("5406.01.220j"),
+            Some(RoomId::Tumonline("5406.01.220J".to_string()))
+        );
+    }
+
+    #[test]
+    fn arch_names() {
+        for q in ["3002@5510", "3002 @ 5510"] {
+            assert_eq!(
+                RoomId::parse(q),
+                Some(RoomId::ArchName("3002@5510".to_string())),
+                "{q}"
+            );
+        }
+        assert_eq!(
+            RoomId::parse("00.06.036@5606"),
+            Some(RoomId::ArchName("00.06.036@5606".to_string()))
+        );
+    }
+
+    #[test]
+    fn prefixed_arch_ids() {
+        let expected = RoomId::PrefixedArchId {
+            building: "mi".to_string(),
+            arch_id: "00.08.038".to_string(),
+        };
+        for q in ["MI 00.08.038", "mi 00.08.038", "MI00.08.038"] {
+            assert_eq!(RoomId::parse(q), Some(expected.clone()), "{q}");
+        }
+        assert_eq!(
+            RoomId::parse("MW2001"),
+            Some(RoomId::PrefixedArchId {
+                building: "mw".to_string(),
+                arch_id: "2001".to_string(),
+            })
+        );
+        assert_eq!(
+            RoomId::parse("MW 2.01"),
+            Some(RoomId::PrefixedArchId {
+                building: "mw".to_string(),
+                arch_id: "2.01".to_string(),
+            })
+        );
+        assert_eq!(expected.to_string(), "MI 00.08.038");
+    }
+
+    #[test]
+    fn not_room_ids() {
+        for q in ["", "mi hs 1", "garching", "5606", "interims 1", "wc in:mi"] {
+            assert_eq!(RoomId::parse(q), None, "{q}");
+        }
+        // numbered names of rooms or buildings are not architects ids
+        for q in ["hs1", "wc2", "mw1", "HS 12", "mi 2."] {
+            assert_eq!(RoomId::parse(q), None, "{q}");
+        }
+    }
+}
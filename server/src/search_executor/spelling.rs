@@ -0,0 +1,206 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use cached::proc_macro::cached;
+use sqlx::PgPool;
+
+use crate::db::location::ParentName;
+use crate::db::search_misses::PickedQuery;
+use crate::setup::meilisearch::Synonyms;
+
+/// Words shorter than this are too ambiguous to be corrected (e.g. `mi` vs `mw`)
+const MIN_WORD_LEN: usize = 4;
+/// How often users need to have picked a result for a query to count as frequent
+const MIN_PICKS: i64 = 3;
+
+/// Known words, which misspelled queries are corrected towards, and how common they are
+#[derive(Debug, Default, Clone)]
+pub struct Vocabulary(HashMap<String, usize>);
+
+impl Vocabulary {
+    /// Building names, parent keywords, synonyms and frequently searched queries
+    #[tracing::instrument(skip(pool))]
+    async fn load(pool: &PgPool) -> anyhow::Result<Self> {
+        let mut vocabulary = Self::default();
+        for parent in ParentName::fetch_all(pool).await? {
+            vocabulary.insert_text(&parent.key, 1);
+            vocabulary.insert_text(&parent.name, 1);
+            if let Some(short_name) = parent.short_name {
+                vocabulary.insert_text(&short_name, 1);
+            }
+        }
+        for (word, synonyms) in Synonyms::try_load()?.0 {
+            vocabulary.insert_text(&word, 1);
+            for synonym in synonyms {
+                vocabulary.insert_text(&synonym, 1);
+            }
+        }
+        for picked in PickedQuery::fetch_frequent(pool, MIN_PICKS).await? {
+            let picks = usize::try_from(picked.picks).unwrap_or_default();
+            vocabulary.insert_text(&picked.query, picks);
+        }
+        Ok(vocabulary)
+    }
+
+    /// Adds all correctable words of `text`, each weighted by `weight`
+    fn insert_text(&mut self, text: &str, weight: usize) {
+        let words = text
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .map(str::to_lowercase)
+            .filter(|word| is_correctable(word));
+        for word in words {
+            *self.0.entry(word).or_default() += weight;
+        }
+    }
+
+    /// The corrected query, if any word of `q` is likely misspelled
+    ///
+    /// Filters (e.g. `in:garching`) and words containing digits (e.g. room codes) are left as they are.
+    pub fn correct(&self, q: &str) -> Option<String> {
+        let mut changed = false;
+        let corrected = q
+            .split_whitespace()
+            .map(|word| match self.closest(word) {
+                Some(correction) => {
+                    changed = true;
+                    correction
+                }
+                None => word,
+            })
+            .collect::<Vec<&str>>()
+            .join(" ");
+        changed.then_some(corrected)
+    }
+
+    /// The most common known word with the fewest edits to `word`, if `word` is not known itself
+    fn closest(&self, word: &str) -> Option<&str> {
+        let is_filter = word.contains(':') || word.starts_with(['@', '=', '!']);
+        let word = word.to_lowercase();
+        if is_filter || !is_correctable(&word) || self.0.contains_key(&word) {
+            return None;
+        }
+        let word = word.chars().collect::<Vec<char>>();
+        let max_edits = max_edits(word.len());
+        self.0
+            .iter()
+            .filter(|(known, _)| known.chars().count().abs_diff(word.len()) <= max_edits)
+            .filter_map(|(known, weight)| {
+                let known_chars = known.chars().collect::<Vec<char>>();
+                let edits = edit_distance(&word, &known_chars);
+                (edits <= max_edits).then_some((edits, Reverse(*weight), known.as_str()))
+            })
+            .min()
+            .map(|(_, _, known)| known)
+    }
+}
+
+fn is_correctable(word: &str) -> bool {
+    word.chars().count() >= MIN_WORD_LEN && !word.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Meilisearch already tolerates up to two typos, so we need to be a bit more generous
+fn max_edits(word_len: usize) -> usize {
+    match word_len {
+        0..5 => 1,
+        5..8 => 2,
+        _ => 3,
+    }
+}
+
+/// Optimal string alignment distance, meaning that swapping two adjacent characters counts as one edit
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution_cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution_cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+// the vocabulary only changes when new data is loaded or users search a lot => refreshing hourly is enough
+#[cached(time = 3600, key = "()", convert = "{}", result = true)]
+pub async fn cached_vocabulary(pool: &PgPool) -> anyhow::Result<Vocabulary> {
+    Vocabulary::load(pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn vocabulary() -> Vocabulary {
+        let mut vocabulary = Vocabulary::default();
+        vocabulary.insert_text("garching", 1);
+        vocabulary.insert_text("Garching Forschungszentrum", 1);
+        vocabulary.insert_text("Mathematik / Informatik", 1);
+        vocabulary.insert_text("Hörsaal", 1);
+        vocabulary.insert_text("garching-hochbrueck", 1);
+        vocabulary.insert_text("bibliothek", 5);
+        vocabulary.insert_text("bibliotheken", 1);
+        vocabulary
+    }
+
+    #[test]
+    fn edit_distances() {
+        let distance = |a: &str, b: &str| {
+            edit_distance(
+                &a.chars().collect::<Vec<char>>(),
+                &b.chars().collect::<Vec<char>>(),
+            )
+        };
+        assert_eq!(distance("garching", "garching"), 0);
+        assert_eq!(distance("sfarching", "garching"), 2);
+        assert_eq!(distance("garhcing", "garching"), 1);
+        assert_eq!(distance("horsaal", "hörsaal"), 1);
+        assert_eq!(distance("", "mi"), 2);
+    }
+
+    #[test]
+    fn misspelled_words_are_corrected() {
+        let vocabulary = vocabulary();
+        assert_eq!(
+            vocabulary.correct("sfarching"),
+            Some("garching".to_string())
+        );
+        assert_eq!(
+            vocabulary.correct("Mathematk hs1"),
+            Some("mathematik hs1".to_string())
+        );
+        assert_eq!(vocabulary.correct("horsaal"), Some("hörsaal".to_string()));
+        assert_eq!(
+            vocabulary.correct("garching-hochbrück"),
+            Some("garching-hochbrueck".to_string())
+        );
+        // the more common word wins if both are equally close
+        assert_eq!(
+            vocabulary.correct("bibliotheke"),
+            Some("bibliothek".to_string())
+        );
+    }
+
+    #[test]
+    fn known_words_and_filters_are_kept() {
+        let vocabulary = vocabulary();
+        assert_eq!(vocabulary.correct("garching"), None);
+        assert_eq!(vocabulary.correct("Garching hs1"), None);
+        assert_eq!(vocabulary.correct("in:sfarching"), None);
+        assert_eq!(vocabulary.correct("5606.EG.036"), None);
+        assert_eq!(vocabulary.correct("mw"), None);
+        assert_eq!(vocabulary.correct("zzzzzzzz"), None);
+    }
+}
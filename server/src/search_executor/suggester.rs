@@ -0,0 +1,357 @@
+use meilisearch_sdk::client::Client;
+use serde::Serialize;
+
+use super::parser::ParsedQuery;
+use crate::external::meilisearch::{suggest_entries, suggest_facet_values, MSHit};
+
+/// Filter keywords which are suggested while they are being typed
+const FILTER_KEYWORDS: [&str; 5] = ["in:", "usage:", "type:", "floor:", "operator:"];
+/// All types an entry can have
+const TYPES: [&str; 8] = [
+    "area",
+    "building",
+    "campus",
+    "joined_building",
+    "poi",
+    "room",
+    "site",
+    "virtual_room",
+];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    /// A filter keyword like `in:`, `usage:`, `type:`, `floor:` or `operator:`
+    FilterKeyword,
+    /// A valid value of a filter like `in:garching`
+    FilterValue,
+    /// A location (room, building, ...)
+    Location,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, utoipa::ToSchema)]
+pub struct Suggestion {
+    /// The full query, with the last token completed
+    #[schema(example = "wc in:garching")]
+    query: String,
+    /// Text to display for this suggestion
+    #[schema(example = "in:garching")]
+    label: String,
+    /// What was completed
+    kind: SuggestionKind,
+    /// The id of the location.
+    ///
+    /// Only present for `location` suggestions.
+    #[schema(example = "5606.EG.036")]
+    id: Option<String>,
+}
+
+/// What the filter in the last token is filtering by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterKind {
+    Parent,
+    Usage,
+    Type,
+    Floor,
+    Operator,
+}
+
+/// A (partial) filter like `-usage:w` the user is currently typing
+#[derive(Debug, PartialEq, Eq)]
+struct PartialFilter<'a> {
+    /// everything before the value, e.g. `-usage:`
+    keyword: &'a str,
+    kind: FilterKind,
+    /// the partial value, e.g. `w`
+    value: &'a str,
+}
+
+impl<'a> PartialFilter<'a> {
+    fn parse(token: &'a str) -> Option<Self> {
+        let without_negation = token
+            .strip_prefix("not:")
+            .or_else(|| token.strip_prefix('-'))
+            .or_else(|| token.strip_prefix('!'))
+            .unwrap_or(token);
+        let negation = &token[..token.len() - without_negation.len()];
+        [
+            ("in:", FilterKind::Parent),
+            ("@", FilterKind::Parent),
+            ("usage:", FilterKind::Usage),
+            ("nutzung:", FilterKind::Usage),
+            ("=", FilterKind::Usage),
+            ("type:", FilterKind::Type),
+            ("typ:", FilterKind::Type),
+            ("floor:", FilterKind::Floor),
+            ("etage:", FilterKind::Floor),
+            ("level:", FilterKind::Floor),
+            ("operator:", FilterKind::Operator),
+            ("org:", FilterKind::Operator),
+        ]
+        .into_iter()
+        .find_map(|(keyword, kind)| {
+            let value = without_negation.strip_prefix(keyword)?;
+            Some(PartialFilter {
+                keyword: &token[..negation.len() + keyword.len()],
+                kind,
+                value: value.trim_start(),
+            })
+        })
+    }
+}
+
+/// Splits the query into the part before the last token and the last (partial) token
+///
+/// Filters with a space between the keyword and the value (e.g. `in: garching`) are kept together.
+fn split_last_token(q: &str) -> (&str, &str) {
+    let q = q.trim_start();
+    let Some((i, c)) = q.char_indices().rev().find(|(_, c)| c.is_whitespace()) else {
+        return ("", q);
+    };
+    let (prefix, last) = (q[..i].trim_end(), &q[i + c.len_utf8()..]);
+    let word_start = prefix
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let previous_word = &prefix[word_start..];
+    let is_bare_filter_keyword = previous_word.ends_with(':')
+        || previous_word.ends_with('@')
+        || previous_word.ends_with('=');
+    if is_bare_filter_keyword {
+        (prefix[..word_start].trim_end(), q[word_start..].trim_end())
+    } else {
+        (prefix, last)
+    }
+}
+
+/// Replaces the last token of the query with the `completion`
+fn complete(prefix: &str, completion: &str) -> String {
+    if prefix.is_empty() {
+        completion.to_string()
+    } else {
+        format!("{prefix} {completion}")
+    }
+}
+
+fn keyword_suggestions(prefix: &str, last: &str) -> Vec<Suggestion> {
+    FILTER_KEYWORDS
+        .iter()
+        .filter(|keyword| keyword.starts_with(&last.to_lowercase()) && keyword.len() > last.len())
+        .map(|keyword| Suggestion {
+            query: complete(prefix, keyword),
+            label: keyword.to_string(),
+            kind: SuggestionKind::FilterKeyword,
+            id: None,
+        })
+        .collect()
+}
+
+fn filter_value_suggestions(
+    prefix: &str,
+    filter: &PartialFilter,
+    values: &[String],
+) -> Vec<Suggestion> {
+    values
+        .iter()
+        .map(|value| {
+            let completion = format!("{keyword}{value}", keyword = filter.keyword);
+            Suggestion {
+                query: complete(prefix, &completion),
+                label: completion,
+                kind: SuggestionKind::FilterValue,
+                id: None,
+            }
+        })
+        .collect()
+}
+
+/// The text the partial `last` token should be completed to for this `hit`
+///
+/// Room codes and arch names are preferred over the words of the name, as they are the most common ids
+fn location_completion(hit: &MSHit, last: &str) -> String {
+    let last = last.to_lowercase();
+    if hit.room_code.to_lowercase().starts_with(&last) {
+        return hit.room_code.clone();
+    }
+    if let Some(arch_name) = &hit.arch_name {
+        if arch_name.to_lowercase().starts_with(&last) {
+            return arch_name.clone();
+        }
+    }
+    hit.name
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .find(|word| word.to_lowercase().starts_with(&last))
+        .unwrap_or(hit.room_code.as_str())
+        .to_string()
+}
+
+#[tracing::instrument(skip(client))]
+pub(super) async fn suggest(
+    client: &Client,
+    q: &str,
+    limit: usize,
+) -> Result<Vec<Suggestion>, meilisearch_sdk::errors::Error> {
+    let (prefix, last) = split_last_token(q);
+    if last.is_empty() {
+        return Ok(vec![]);
+    }
+
+    if let Some(filter) = PartialFilter::parse(last) {
+        let values = match filter.kind {
+            FilterKind::Type => TYPES
+                .iter()
+                .filter(|t| t.starts_with(&filter.value.to_lowercase().replace('-', "_")))
+                .take(limit)
+                .map(|t| t.to_string())
+                .collect(),
+            FilterKind::Usage => suggest_facet_values(client, "usage", filter.value, limit).await?,
+            FilterKind::Parent => {
+                suggest_facet_values(client, "parent_keywords", filter.value, limit).await?
+            }
+            FilterKind::Floor => suggest_facet_values(client, "floor", filter.value, limit).await?,
+            FilterKind::Operator => {
+                suggest_facet_values(client, "operator_code", filter.value, limit).await?
+            }
+        };
+        return Ok(filter_value_suggestions(prefix, &filter, &values));
+    }
+
+    let mut suggestions = keyword_suggestions(prefix, last);
+    let parsed_prefix = ParsedQuery::from(prefix);
+    let query = complete(&parsed_prefix.as_meilisearch_query(), last);
+    let filter = parsed_prefix.filters.as_meilisearch_filters();
+    let sorting = parsed_prefix.sorting.as_meilisearch_sorting();
+    let results = suggest_entries(client, &query, &filter, &sorting, limit).await?;
+    for hit in results.hits {
+        let completion = location_completion(&hit.result, last);
+        let query = complete(prefix, &completion);
+        if suggestions.iter().any(|s| s.query == query) {
+            continue;
+        }
+        suggestions.push(Suggestion {
+            query,
+            label: hit.result.name,
+            kind: SuggestionKind::Location,
+            id: Some(hit.result.room_code),
+        });
+    }
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(split_last_token(""), ("", ""));
+        assert_eq!(split_last_token("560"), ("", "560"));
+        assert_eq!(split_last_token("mi hs"), ("mi", "hs"));
+        assert_eq!(split_last_token("  mi   hs"), ("mi", "hs"));
+        assert_eq!(split_last_token("mi hs "), ("mi hs", ""));
+        assert_eq!(split_last_token("wc in: garch"), ("wc", "in: garch"));
+        assert_eq!(split_last_token("in: garch"), ("", "in: garch"));
+        assert_eq!(split_last_token("wc in: "), ("wc", "in:"));
+        assert_eq!(split_last_token("Griaß eich"), ("Griaß", "eich"));
+    }
+
+    #[test]
+    fn partial_filters() {
+        assert_eq!(PartialFilter::parse("560"), None);
+        assert_eq!(
+            PartialFilter::parse("in:garch"),
+            Some(PartialFilter {
+                keyword: "in:",
+                kind: FilterKind::Parent,
+                value: "garch",
+            })
+        );
+        assert_eq!(
+            PartialFilter::parse("in: garch"),
+            Some(PartialFilter {
+                keyword: "in:",
+                kind: FilterKind::Parent,
+                value: "garch",
+            })
+        );
+        assert_eq!(
+            PartialFilter::parse("not:usage:"),
+            Some(PartialFilter {
+                keyword: "not:usage:",
+                kind: FilterKind::Usage,
+                value: "",
+            })
+        );
+        assert_eq!(
+            PartialFilter::parse("org:tus"),
+            Some(PartialFilter {
+                keyword: "org:",
+                kind: FilterKind::Operator,
+                value: "tus",
+            })
+        );
+        assert_eq!(
+            PartialFilter::parse("!type:jo"),
+            Some(PartialFilter {
+                keyword: "!type:",
+                kind: FilterKind::Type,
+                value: "jo",
+            })
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        let suggestions = keyword_suggestions("wc", "ty");
+        assert_eq!(
+            suggestions,
+            vec![Suggestion {
+                query: "wc type:".to_string(),
+                label: "type:".to_string(),
+                kind: SuggestionKind::FilterKeyword,
+                id: None,
+            }]
+        );
+        assert_eq!(keyword_suggestions("", "type:"), vec![]);
+        assert_eq!(keyword_suggestions("", "hörsaal"), vec![]);
+    }
+
+    #[test]
+    fn filter_values() {
+        let filter = PartialFilter::parse("-in:garch").unwrap();
+        let values = ["garching".to_string(), "garching-hochbrueck".to_string()];
+        let queries = filter_value_suggestions("wc", &filter, &values)
+            .into_iter()
+            .map(|s| s.query)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queries,
+            vec!["wc -in:garching", "wc -in:garching-hochbrueck"]
+        );
+    }
+
+    #[test]
+    fn location_completions() {
+        let hit: MSHit = serde_json::from_value(serde_json::json!({
+            "ms_id": "5606-EG-036",
+            "room_code": "5606.EG.036",
+            "name": "5606.EG.036 (Büro Fachschaft Mathe Physik Informatik Chemie / MPIC)",
+            "arch_name": "00.06.036@5606",
+            "type": "room",
+            "type_common_name": "Büro",
+            "parent_building_names": ["Finger 06 (BT06)"],
+            "parent_keywords": ["mi", "5606"],
+            "rank": 100,
+        }))
+        .unwrap();
+        assert_eq!(location_completion(&hit, "5606.EG.0"), "5606.EG.036");
+        assert_eq!(location_completion(&hit, "00.06"), "00.06.036@5606");
+        assert_eq!(location_completion(&hit, "fachsch"), "Fachschaft");
+        assert_eq!(location_completion(&hit, "fsmpic"), "5606.EG.036");
+    }
+}
@@ -1,20 +1,32 @@
 use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
-use meilisearch_sdk::client::Client;
+use meilisearch_sdk::client::{Client, SwapIndexes};
+use meilisearch_sdk::errors::{Error, ErrorCode};
 use meilisearch_sdk::settings::Settings;
 use meilisearch_sdk::tasks::Task;
 use serde_json::Value;
-use tracing::{debug, error, info};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::{debug, error, info, warn};
 
 const TIMEOUT: Option<Duration> = Some(Duration::from_secs(60));
 const POLLING_RATE: Option<Duration> = Some(Duration::from_millis(250));
+/// The index, which is searched
+const LIVE_INDEX: &str = "entries";
+/// The index, which is filled while reindexing and then swapped with the [`LIVE_INDEX`]
+const REINDEX_INDEX: &str = "entries_reindex";
+/// Reindexing is aborted, if the new index would contain less than this share of the previous documents
+const MIN_DOCUMENT_RATIO: f64 = 0.8;
+
+/// Only one reindex may run at a time, as they share the [`REINDEX_INDEX`]
+static REINDEXING: LazyLock<Arc<Mutex<()>>> = LazyLock::new(|| Arc::new(Mutex::new(())));
 
 #[derive(serde::Deserialize)]
-struct Synonyms(HashMap<String, Vec<String>>);
+pub(crate) struct Synonyms(pub HashMap<String, Vec<String>>);
 
 impl Synonyms {
-    fn try_load() -> Result<Self, serde_yaml::Error> {
+    pub(crate) fn try_load() -> Result<Self, serde_yaml::Error> {
         serde_yaml::from_str(include_str!("search_synonyms.yaml"))
     }
 }
@@ -52,12 +64,27 @@ pub async fn setup(client: &Client) -> anyhow::Result<()> {
     wait_for_healthy(client).await;
     info!("Meilisearch is healthy");
 
+    // the settings and documents are only loaded via reindexing, to not affect the live index
+    create_index(client, LIVE_INDEX).await
+}
+
+/// Creates the index `uid`, if it does not exist yet
+#[tracing::instrument(skip(client))]
+async fn create_index(client: &Client, uid: &str) -> anyhow::Result<()> {
+    // creating an existing index fails, which is fine
     client
-        .create_index("entries", Some("ms_id"))
+        .create_index(uid, Some("ms_id"))
         .await?
         .wait_for_completion(client, POLLING_RATE, TIMEOUT)
         .await?;
-    let entries = client.index("entries");
+    Ok(())
+}
+
+/// Creates the index `uid` (if it does not exist yet) and applies our settings to it
+#[tracing::instrument(skip(client))]
+async fn configure_index(client: &Client, uid: &str) -> anyhow::Result<()> {
+    create_index(client, uid).await?;
+    let index = client.index(uid);
 
     let settings = Settings::new()
         .with_filterable_attributes([
@@ -67,6 +94,12 @@ pub async fn setup(client: &Client) -> anyhow::Result<()> {
             "campus",
             "type",
             "usage",
+            "floor",
+            "operator_code",
+            "operator_id",
+            "has_calendar",
+            "room_code",
+            "_geo",
         ])
         .with_ranking_rules([
             "words",
@@ -82,11 +115,14 @@ pub async fn setup(client: &Client) -> anyhow::Result<()> {
             "room_code",
             "room_code_normalised",
             "name",
+            "name_en",
             "arch_name",
             "arch_name_normalised",
             "type",
             "type_common_name",
+            "type_common_name_en",
             "parent_building_names",
+            "parent_building_names_en",
             "parent_keywords",
             "usage",
             "address",
@@ -94,34 +130,214 @@ pub async fn setup(client: &Client) -> anyhow::Result<()> {
         ])
         .with_synonyms(Synonyms::try_load()?.0);
 
-    let res = entries
+    let res = index
         .set_settings(&settings)
         .await?
         .wait_for_completion(client, POLLING_RATE, TIMEOUT)
         .await?;
     if let Task::Failed { content } = res {
-        panic!("Failed to add settings to Meilisearch: {content:?}");
+        anyhow::bail!("Failed to add settings to Meilisearch: {content:?}");
     }
     Ok(())
 }
-#[tracing::instrument(skip(client))]
-pub async fn load_data(client: &Client) -> anyhow::Result<()> {
-    let entries = client.index("entries");
+
+#[tracing::instrument]
+async fn fetch_documents() -> anyhow::Result<Vec<Value>> {
     let cdn_url = std::env::var("CDN_URL").unwrap_or_else(|_| "https://nav.tum.de/cdn".to_string());
     let documents = reqwest::get(format!("{cdn_url}/search_data.json"))
         .await?
         .error_for_status()?
         .json::<Vec<Value>>()
         .await?;
-    let res = entries
-        .add_documents(&documents, Some("ms_id"))
+    Ok(documents)
+}
+
+#[tracing::instrument(skip(client, documents))]
+async fn add_documents(client: &Client, uid: &str, documents: &[Value]) -> anyhow::Result<()> {
+    let res = client
+        .index(uid)
+        .add_documents(documents, Some("ms_id"))
         .await?
         .wait_for_completion(client, POLLING_RATE, TIMEOUT)
         .await?;
     if let Task::Failed { content } = res {
-        panic!("Failed to add documents to Meilisearch: {content:?}");
+        anyhow::bail!("Failed to add documents to Meilisearch: {content:?}");
     }
 
     info!("{cnt} documents added", cnt = documents.len());
     Ok(())
 }
+
+/// Number of documents before and after a reindex
+#[derive(Debug, Clone, Copy)]
+pub struct ReindexReport {
+    pub previous_documents: usize,
+    pub documents: usize,
+}
+impl ReindexReport {
+    /// Whether the new index may replace the live one, given that `loaded` documents were added to it
+    fn ensure_plausible(&self, loaded: usize) -> anyhow::Result<()> {
+        if self.documents != loaded {
+            anyhow::bail!(
+                "the new index contains {} documents, but {loaded} were loaded",
+                self.documents,
+            );
+        }
+        // protects against publishing incomplete data
+        if (self.documents as f64) < MIN_DOCUMENT_RATIO * (self.previous_documents as f64) {
+            anyhow::bail!(
+                "the new index contains {} documents, which is suspiciously less than the {} documents of the live index",
+                self.documents,
+                self.previous_documents
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Permission to reindex, as long as it is held no other reindex can start
+pub struct ReindexPermit(OwnedMutexGuard<()>);
+
+/// The permission to reindex, if no other reindex is currently running
+pub fn try_start_reindexing() -> Option<ReindexPermit> {
+    Arc::clone(&REINDEXING)
+        .try_lock_owned()
+        .ok()
+        .map(ReindexPermit)
+}
+
+/// The permission to reindex, once the currently running reindex finished
+pub async fn start_reindexing() -> ReindexPermit {
+    ReindexPermit(Arc::clone(&REINDEXING).lock_owned().await)
+}
+
+/// Rebuilds the live index without downtime, waiting for a running reindex to finish first
+///
+/// See [`reindex_with`].
+pub async fn reindex(client: &Client) -> anyhow::Result<ReindexReport> {
+    reindex_with(client, start_reindexing().await).await
+}
+
+/// Rebuilds the live index without downtime
+///
+/// The settings and documents are loaded into a temporary index first.
+/// Only if its number of documents is plausible, it is atomically swapped with the live index.
+/// Thus, search is never affected while indexing, and documents removed from the data are deleted.
+#[tracing::instrument(skip(client, _permit))]
+pub async fn reindex_with(
+    client: &Client,
+    _permit: ReindexPermit,
+) -> anyhow::Result<ReindexReport> {
+    // swapping requires both indexes to exist
+    create_index(client, LIVE_INDEX).await?;
+    // a previous reindex might have been interrupted
+    delete_index(client, REINDEX_INDEX).await?;
+
+    let result = load_and_swap(client).await;
+    if result.is_ok() {
+        // cached results might reference entries, which were removed or changed
+        crate::routes::search::clear_search_cache().await;
+    }
+    // after the swap, the temporary index contains the previous documents
+    // if it cannot be deleted now, this happens before the next reindex
+    if let Err(e) = delete_index(client, REINDEX_INDEX).await {
+        warn!(error = ?e, "could not delete the temporary index");
+    }
+    let report = result?;
+    info!(?report, "reindexed meilisearch");
+    Ok(report)
+}
+
+async fn load_and_swap(client: &Client) -> anyhow::Result<ReindexReport> {
+    let documents = fetch_documents().await?;
+    configure_index(client, REINDEX_INDEX).await?;
+    add_documents(client, REINDEX_INDEX, &documents).await?;
+
+    let report = ReindexReport {
+        previous_documents: client
+            .index(LIVE_INDEX)
+            .get_stats()
+            .await?
+            .number_of_documents,
+        documents: client
+            .index(REINDEX_INDEX)
+            .get_stats()
+            .await?
+            .number_of_documents,
+    };
+    report.ensure_plausible(documents.len())?;
+
+    let res = client
+        .swap_indexes([&SwapIndexes {
+            indexes: (LIVE_INDEX.to_string(), REINDEX_INDEX.to_string()),
+        }])
+        .await?
+        .wait_for_completion(client, POLLING_RATE, TIMEOUT)
+        .await?;
+    if let Task::Failed { content } = res {
+        anyhow::bail!("Failed to swap the indexes: {content:?}");
+    }
+    Ok(report)
+}
+
+/// Deletes the index `uid`, if it exists
+#[tracing::instrument(skip(client))]
+async fn delete_index(client: &Client, uid: &str) -> anyhow::Result<()> {
+    match client.get_index(uid).await {
+        Ok(_) => {}
+        Err(Error::Meilisearch(e)) if matches!(e.error_code, ErrorCode::IndexNotFound) => {
+            return Ok(())
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let res = client
+        .delete_index(uid)
+        .await?
+        .wait_for_completion(client, POLLING_RATE, TIMEOUT)
+        .await?;
+    if let Task::Failed { content } = res {
+        anyhow::bail!("Failed to delete the index {uid}: {content:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(previous_documents: usize, documents: usize) -> ReindexReport {
+        ReindexReport {
+            previous_documents,
+            documents,
+        }
+    }
+
+    #[test]
+    fn plausible_indexes_are_swapped() {
+        assert!(report(1_000, 1_000).ensure_plausible(1_000).is_ok());
+        assert!(report(1_000, 800).ensure_plausible(800).is_ok());
+        // growing is always fine
+        assert!(report(1_000, 5_000).ensure_plausible(5_000).is_ok());
+        // the live index is empty on the first start
+        assert!(report(0, 1_000).ensure_plausible(1_000).is_ok());
+    }
+
+    #[test]
+    fn shrinking_indexes_are_not_swapped() {
+        assert!(report(1_000, 799).ensure_plausible(799).is_err());
+        assert!(report(1_000, 0).ensure_plausible(0).is_err());
+    }
+
+    #[test]
+    fn incompletely_added_documents_are_not_swapped() {
+        assert!(report(1_000, 1_000).ensure_plausible(1_200).is_err());
+    }
+
+    #[tokio::test]
+    async fn only_one_reindex_can_start() {
+        // other tests might be reindexing concurrently
+        let permit = start_reindexing().await;
+        assert!(try_start_reindexing().is_none());
+        drop(permit);
+    }
+}
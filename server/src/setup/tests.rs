@@ -87,7 +87,7 @@ async fn test_db_setup() {
 #[tracing_test::traced_test]
 async fn test_meilisearch_setup() {
     let ms = MeiliSearchTestContainer::new().await;
-    crate::setup::meilisearch::load_data(&ms.client)
+    crate::setup::meilisearch::reindex(&ms.client)
         .await
         .unwrap();
 }